@@ -0,0 +1,4 @@
+// Embedded migrations are read at compile time, pick up new ones without a clean build
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,8 @@
+-- Latest TGTG credentials, kept up to date on every token refresh
+CREATE TABLE IF NOT EXISTS credentials (
+    id                           INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    access_token                 TEXT NOT NULL,
+    refresh_token                TEXT NOT NULL,
+    user_id                      TEXT NOT NULL,
+    cookie                       TEXT NOT NULL
+);
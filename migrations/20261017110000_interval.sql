@@ -0,0 +1,2 @@
+-- Polling interval per channel in seconds
+ALTER TABLE channels ADD COLUMN poll_interval INTEGER NOT NULL DEFAULT 60;
@@ -0,0 +1,2 @@
+-- Active hours of a channel, formatted like `08:00-22:00 mon,tue Europe/Amsterdam`
+ALTER TABLE channels ADD COLUMN schedule TEXT;
@@ -0,0 +1,8 @@
+-- Listing messages posted by the monitors, kept across restarts
+CREATE TABLE IF NOT EXISTS item_messages (
+    channel_id                   TEXT NOT NULL,
+    item_id                      TEXT NOT NULL,
+    message_id                   TEXT NOT NULL,
+    quantity                     INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, item_id)
+);
@@ -0,0 +1,10 @@
+-- Named locations watched by a channel next to its main location
+CREATE TABLE IF NOT EXISTS watches (
+    channel_id                   TEXT NOT NULL,
+    name                         TEXT NOT NULL,
+    latitude                     REAL NOT NULL,
+    longitude                    REAL NOT NULL,
+    radius                       INTEGER NOT NULL,
+    regex                        TEXT,
+    PRIMARY KEY (channel_id, name)
+);
@@ -0,0 +1,17 @@
+-- Every appearance of a listing in a channel, from first seen until sold out
+CREATE TABLE IF NOT EXISTS listing_history (
+    id                           INTEGER PRIMARY KEY NOT NULL,
+    channel_id                   TEXT NOT NULL,
+    item_id                      TEXT NOT NULL,
+    store_name                   TEXT NOT NULL,
+    display_name                 TEXT NOT NULL,
+    price_code                   TEXT NOT NULL,
+    price_minor_units            INTEGER NOT NULL,
+    price_decimals               INTEGER NOT NULL,
+    quantity                     INTEGER NOT NULL,
+    pickup_start                 INTEGER,
+    pickup_end                   INTEGER,
+    first_seen                   INTEGER NOT NULL,
+    sold_out                     INTEGER
+);
+CREATE INDEX IF NOT EXISTS listing_history_channel ON listing_history (channel_id, first_seen);
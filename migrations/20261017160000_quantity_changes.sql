@@ -0,0 +1,14 @@
+-- Quantity of a listing in a channel every time it changed, zero once sold out
+CREATE TABLE IF NOT EXISTS quantity_changes (
+    id                           INTEGER PRIMARY KEY NOT NULL,
+    channel_id                   TEXT NOT NULL,
+    item_id                      TEXT NOT NULL,
+    store_name                   TEXT NOT NULL,
+    store_time_zone              TEXT NOT NULL,
+    price_code                   TEXT NOT NULL,
+    price_minor_units            INTEGER NOT NULL,
+    price_decimals               INTEGER NOT NULL,
+    quantity                     INTEGER NOT NULL,
+    observed_at                  INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS quantity_changes_channel ON quantity_changes (channel_id, observed_at);
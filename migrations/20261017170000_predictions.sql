@@ -0,0 +1,2 @@
+-- Opt-in heads-ups before the typical release time of the stores seen in a channel
+ALTER TABLE channels ADD COLUMN predictions INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Price range of the listings shown in a channel, in major units of the currency if given
+ALTER TABLE channels ADD COLUMN min_price REAL;
+ALTER TABLE channels ADD COLUMN max_price REAL;
+ALTER TABLE channels ADD COLUMN price_currency TEXT;
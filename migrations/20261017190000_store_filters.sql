@@ -0,0 +1,10 @@
+-- Stores always or never shown in a channel, by TGTG store id
+CREATE TABLE IF NOT EXISTS store_filters (
+    channel_id                   TEXT NOT NULL,
+    store_id                     TEXT NOT NULL,
+    store_name                   TEXT NOT NULL,
+    allowed                      INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, store_id)
+);
+-- Store of each appearance, to pick the stores seen in a channel from
+ALTER TABLE listing_history ADD COLUMN store_id TEXT;
@@ -0,0 +1,8 @@
+-- Regexes on the listing name become filter expressions like `name ~ "(?-i)bakery"`, which keep
+-- matching case sensitively as `~` ignores the case otherwise
+ALTER TABLE channels RENAME COLUMN regex TO filter;
+ALTER TABLE watches RENAME COLUMN regex TO filter;
+UPDATE channels SET filter = 'name ~ "(?-i)' || replace(replace(filter, '\', '\\'), '"', '\"') || '"'
+WHERE filter IS NOT NULL;
+UPDATE watches SET filter = 'name ~ "(?-i)' || replace(replace(filter, '\', '\\'), '"', '\"') || '"'
+WHERE filter IS NOT NULL;
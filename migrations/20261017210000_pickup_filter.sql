@@ -0,0 +1,2 @@
+-- Pickup hours and day of the listings shown in a channel, like 17:00-21:00 today
+ALTER TABLE channels ADD COLUMN pickup TEXT;
@@ -0,0 +1,16 @@
+-- Role mentioned on new listings of a channel
+ALTER TABLE channels ADD COLUMN mention_role TEXT;
+-- Users getting a DM for the new listings of a channel matching their filter
+CREATE TABLE IF NOT EXISTS subscriptions (
+    channel_id                   TEXT NOT NULL,
+    user_id                      TEXT NOT NULL,
+    filter                       TEXT,
+    PRIMARY KEY (channel_id, user_id)
+);
+-- Last time the subscribers of a channel were notified of an item, for the cooldown
+CREATE TABLE IF NOT EXISTS notifications (
+    channel_id                   TEXT NOT NULL,
+    item_id                      TEXT NOT NULL,
+    notified_at                  INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, item_id)
+);
@@ -0,0 +1,2 @@
+-- Listing events posting a notification in a channel, comma separated
+ALTER TABLE channels ADD COLUMN notify TEXT NOT NULL DEFAULT 'new';
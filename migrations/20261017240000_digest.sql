@@ -0,0 +1,9 @@
+-- Order of the listings when a channel shows them in a digest, no digest without it
+ALTER TABLE channels ADD COLUMN digest TEXT;
+-- Summary messages of the channels in digest mode, by zero based page
+CREATE TABLE IF NOT EXISTS digest_messages (
+    channel_id                   TEXT NOT NULL,
+    page                         INTEGER NOT NULL,
+    message_id                   TEXT NOT NULL,
+    PRIMARY KEY (channel_id, page)
+);
@@ -0,0 +1,11 @@
+-- Whether a channel posts the listings of each store in a thread of its own
+ALTER TABLE channels ADD COLUMN threads INTEGER NOT NULL DEFAULT 0;
+-- Thread of each store under a channel, created on first sight of the store
+CREATE TABLE IF NOT EXISTS store_threads (
+    channel_id                   TEXT NOT NULL,
+    store_id                     TEXT NOT NULL,
+    thread_id                    TEXT NOT NULL,
+    PRIMARY KEY (channel_id, store_id)
+);
+-- Thread a listing message was posted in, the channel itself without one
+ALTER TABLE item_messages ADD COLUMN thread_id TEXT;
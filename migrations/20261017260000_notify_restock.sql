@@ -0,0 +1,2 @@
+-- Restocks notify by default like new listings, as they did before the events could be chosen
+UPDATE channels SET notify = 'new,restock' WHERE notify = 'new';
@@ -0,0 +1,4 @@
+-- Time an appearance ended without selling out, as its purchase window passed or it was left out
+ALTER TABLE listing_history ADD COLUMN ended INTEGER;
+-- Whether a zero quantity closes an appearance which ended without selling out
+ALTER TABLE quantity_changes ADD COLUMN ended INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Schedules starting and ending at the same time never let a channel poll, they aren't accepted
+-- anymore and are removed
+UPDATE channels SET schedule = NULL WHERE substr(schedule, 1, 5) = substr(schedule, 7, 5);
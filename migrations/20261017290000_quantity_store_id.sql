@@ -0,0 +1,7 @@
+-- Store of a quantity change, as stores of a chain share their name
+ALTER TABLE quantity_changes ADD COLUMN store_id TEXT;
+UPDATE quantity_changes SET store_id = (
+    SELECT h.store_id FROM listing_history h
+    WHERE h.channel_id = quantity_changes.channel_id AND h.item_id = quantity_changes.item_id AND h.store_id IS NOT NULL
+    LIMIT 1
+);
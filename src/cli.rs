@@ -0,0 +1,80 @@
+use anyhow::Context as _;
+
+use crate::db::BotDB;
+use crate::tgtg::EmailLogin;
+
+const USAGE: &str = "Usage: tgtg-discord-bot [login <email> | migrate]";
+
+/// One-off commands run from the command line instead of starting the bot.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Logs in to TGTG by email and stores the credentials in the database
+    Login { email: String },
+    /// Upgrades the database schema without starting the bot
+    Migrate,
+}
+
+impl Command {
+    /// Parses the command line arguments (without the binary name), `None` starts the bot.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Option<Self>> {
+        match args.next().as_deref() {
+            None => Ok(None),
+            Some("login") => {
+                let email = args.next().context(USAGE)?;
+                Ok(Some(Command::Login { email }))
+            }
+            Some("migrate") => Ok(Some(Command::Migrate)),
+            Some(other) => anyhow::bail!("Unknown command {}. {}", other, USAGE),
+        }
+    }
+
+    pub async fn run(self, bot_db: &BotDB) -> anyhow::Result<()> {
+        match self {
+            Command::Login { email } => {
+                let login = EmailLogin::start(&email).await?;
+                println!(
+                    "Check your mailbox on a PC and click the login link. \
+                    The link won't work on a phone with the TGTG app installed."
+                );
+                let credentials = login.wait().await?;
+                bot_db.set_credentials(&credentials).await?;
+                println!("Logged in to TGTG, the credentials are stored in the database.");
+            }
+            Command::Migrate => {
+                let version = bot_db.migrate().await?;
+                println!("Database schema is up to date at version {}.", version);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        assert_eq!(Command::parse(args(&[]))?, None);
+        assert_eq!(
+            Command::parse(args(&["login", "me@example.com"]))?,
+            Some(Command::Login {
+                email: "me@example.com".to_owned()
+            })
+        );
+        assert_eq!(
+            Command::parse(args(&["migrate"]))?,
+            Some(Command::Migrate)
+        );
+        assert!(Command::parse(args(&["login"])).is_err());
+        assert!(Command::parse(args(&["logout"])).is_err());
+        Ok(())
+    }
+}
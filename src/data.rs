@@ -1,27 +1,48 @@
 use std::{
     collections::{HashMap, HashSet},
+    fmt,
     sync::Arc,
 };
 
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
 use poise::serenity_prelude as serenity;
-use regex::Regex;
 
 use serde::Deserialize;
-use serenity::all::{ChannelId, MessageId};
+use serenity::all::{ChannelId, MessageId, RoleId, UserId};
 use tokio::sync::RwLock;
 
+use crate::filter::Filter;
 use crate::monitor::ChannelMonitor;
+use crate::pickup::PickupFilter;
+use crate::schedule::Schedule;
+use crate::scheduler::Scheduler;
+use crate::tgtg::TGTGClient;
 
 pub static RADIUS_UNIT: &str = "km";
 pub static DEFAULT_RADIUS: u8 = 1;
 pub static OSM_ZOOM_LEVEL: u8 = 15;
+pub static EARTH_RADIUS: f64 = 6371.0;
+/// Polling interval in seconds of a channel unless configured otherwise
+pub static DEFAULT_INTERVAL: u64 = 60;
+/// Shortest polling interval in seconds a channel can configure
+pub static MIN_INTERVAL: u64 = 30;
+/// Longest polling interval in seconds a channel can configure, a day
+pub static MAX_INTERVAL: u64 = 24 * 60 * 60;
 
 #[derive(Clone, Copy)]
 pub struct ItemMessage {
     pub message_id: MessageId,
     pub quantity: usize,
+    /// Thread of the store the message was posted in, if any
+    pub thread_id: Option<ChannelId>,
+}
+
+impl ItemMessage {
+    /// Channel holding the message, its thread or else the monitored channel
+    pub fn channel_id(&self, channel_id: ChannelId) -> ChannelId {
+        self.thread_id.unwrap_or(channel_id)
+    }
 }
 
 #[derive(Clone)]
@@ -29,7 +50,120 @@ pub struct TGTGConfig {
     pub latitude: f64,
     pub longitude: f64,
     pub radius: u8,
-    pub regex: Option<Regex>,
+    pub filter: Option<Filter>,
+    pub interval: u64,
+    pub schedule: Option<Schedule>,
+    pub price: Option<PriceFilter>,
+    pub pickup: Option<PickupFilter>,
+    /// Stores allowed or blocked in the channel. Blocked stores are never shown, and once
+    /// a store is allowed only the allowed ones are.
+    pub stores: Vec<StoreFilter>,
+    /// Whether heads-ups are posted before the stores' usual release times
+    pub predictions: bool,
+    /// Role mentioned when a listing appears in the channel
+    pub mention_role: Option<RoleId>,
+    /// Events posting a notification, the others only edit or delete the listing message
+    pub notify: Vec<ListingEvent>,
+    /// Shows the available listings in pinned summary messages instead of one message each
+    pub digest: Option<DigestSort>,
+    /// Posts the listings of each store in a thread of the channel
+    pub threads: bool,
+    pub watches: Vec<Watch>,
+}
+
+/// Store allowed or blocked in a channel, the name is kept for listing them
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreFilter {
+    pub store_id: String,
+    pub store_name: String,
+    pub allowed: bool,
+}
+
+/// Price range of the listings shown in a channel, in major units like 4.50
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceFilter {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Listings priced in another currency are left out, any currency passes without it
+    pub currency: Option<String>,
+}
+
+impl PriceFilter {
+    pub fn matches(&self, price: &ItemPrice) -> bool {
+        let amount = price.amount();
+        self.currency
+            .as_ref()
+            .is_none_or(|code| code.eq_ignore_ascii_case(&price.code))
+            && self.min.is_none_or(|min| amount >= min)
+            && self.max.is_none_or(|max| amount <= max)
+    }
+}
+
+/// Formats as `2.00 - 5.00 EUR`, open ends like `<= 5.00` and only a currency as `in EUR`
+impl fmt::Display for PriceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max, &self.currency) {
+            (None, None, Some(code)) => return write!(f, "in {}", code),
+            (Some(min), Some(max), _) => write!(f, "{:.2} - {:.2}", min, max)?,
+            (Some(min), None, _) => write!(f, ">= {:.2}", min)?,
+            (None, Some(max), _) => write!(f, "<= {:.2}", max)?,
+            (None, None, None) => write!(f, "any")?,
+        }
+        if let Some(code) = &self.currency {
+            write!(f, " {}", code)?;
+        }
+        Ok(())
+    }
+}
+
+/// Change of a listing noticed by a monitor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ListingEvent {
+    /// Listing appeared for the first time in a while
+    #[name = "new"]
+    New,
+    /// Listing came back shortly after selling out, or its quantity went up
+    #[name = "restock"]
+    Restock,
+    #[name = "quantity drop"]
+    QuantityDrop,
+    #[name = "sold out"]
+    SoldOut,
+}
+
+/// Order of the listings in a digest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum DigestSort {
+    #[name = "distance"]
+    Distance,
+    #[name = "price"]
+    Price,
+}
+
+/// Page of the summary message of a channel in digest mode
+#[derive(Debug, Clone)]
+pub struct DigestPage {
+    pub listings: Vec<TGTGListing>,
+    /// Zero based
+    pub page: usize,
+    pub pages: usize,
+}
+
+/// User getting a DM when a listing matching the filter appears in a channel
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    pub user_id: UserId,
+    pub filter: Option<Filter>,
+}
+
+/// Named location watched by a channel next to its main one, with its own radius and filter
+#[derive(Clone)]
+pub struct Watch {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius: u8,
+    pub filter: Option<Filter>,
 }
 
 impl TGTGConfig {
@@ -38,44 +172,139 @@ impl TGTGConfig {
             latitude,
             longitude,
             radius: DEFAULT_RADIUS,
-            regex: None,
+            filter: None,
+            interval: DEFAULT_INTERVAL,
+            schedule: None,
+            price: None,
+            pickup: None,
+            stores: Vec::new(),
+            predictions: false,
+            mention_role: None,
+            notify: vec![ListingEvent::New, ListingEvent::Restock],
+            digest: None,
+            threads: false,
+            watches: Vec::new(),
         }
     }
 
     pub fn new_with_radius(latitude: f64, longitude: f64, radius: u8) -> Self {
         Self {
-            latitude,
-            longitude,
             radius,
-            regex: None,
+            ..Self::new(latitude, longitude)
         }
     }
 
-    pub fn new_full(latitude: f64, longitude: f64, radius: u8, regex: Regex) -> Self {
+    pub fn new_full(latitude: f64, longitude: f64, radius: u8, filter: Filter) -> Self {
         Self {
-            latitude,
-            longitude,
             radius,
-            regex: Some(regex),
+            filter: Some(filter),
+            ..Self::new(latitude, longitude)
         }
     }
-}
 
-#[derive(Debug)]
-pub struct TGTGBindings {
-    pub client: pyo3::Py<pyo3::PyAny>,
-    pub fetch_func: pyo3::Py<pyo3::PyAny>,
+    /// Great-circle distance in km between the configured location and the given point
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    /// Main location followed by every watch of the channel, as configs sharing the
+    /// channel's settings
+    pub fn areas(&self) -> Vec<TGTGConfig> {
+        let main = TGTGConfig {
+            watches: Vec::new(),
+            ..self.clone()
+        };
+        let watches = self.watches.iter().map(|w| TGTGConfig {
+            latitude: w.latitude,
+            longitude: w.longitude,
+            radius: w.radius,
+            filter: w.filter.clone(),
+            ..main.clone()
+        });
+        std::iter::once(main.clone()).chain(watches).collect()
+    }
+
+    /// Whether the listing lies within the search area and matches the filters at `now`
+    pub fn matches(&self, listing: &TGTGListing, now: DateTime<Utc>) -> bool {
+        let location = &listing.pickup_location.location;
+        let distance = self.distance_to(location.latitude, location.longitude);
+        distance <= self.radius as f64
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|f| f.matches(listing, distance))
+            && self
+                .price
+                .as_ref()
+                .is_none_or(|p| p.matches(&listing.item.price_including_taxes))
+            && self.pickup.as_ref().is_none_or(|p| p.matches(listing, now))
+            && self.allows_store(&listing.store.store_id)
+    }
+
+    /// Whether the store passes the allow and block lists of the channel
+    pub fn allows_store(&self, store_id: &str) -> bool {
+        match self.stores.iter().find(|s| s.store_id == store_id) {
+            Some(filter) => filter.allowed,
+            None => !self.stores.iter().any(|s| s.allowed),
+        }
+    }
+
+    /// Whether the event posts a notification in the channel
+    pub fn notifies(&self, event: ListingEvent) -> bool {
+        self.notify.contains(&event)
+    }
+
+    /// Whether the search area of `other` lies entirely within this one
+    pub fn covers(&self, other: &TGTGConfig) -> bool {
+        self.distance_to(other.latitude, other.longitude) + other.radius as f64
+            <= self.radius as f64 + 1e-6
+    }
 }
 
 #[allow(dead_code)]
 pub struct DiscordData {
     pub bot_db: Arc<crate::db::BotDB>,
     pub active_channels: Arc<RwLock<HashSet<ChannelMonitor>>>,
-    pub tgtg_bindings: Arc<TGTGBindings>,
+    pub scheduler: Arc<Scheduler>,
+    pub tgtg_client: Arc<dyn TGTGClient>,
     pub tgtg_configs: Arc<RwLock<HashMap<ChannelId, TGTGConfig>>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Appearance of a listing in a channel, from first seen until sold out or ended
+#[derive(Debug, Clone)]
+pub struct ListingRecord {
+    pub store_name: String,
+    pub display_name: String,
+    pub price: ItemPrice,
+    /// Highest quantity seen
+    pub quantity: usize,
+    pub pickup_interval: Option<PickupInterval>,
+    pub first_seen: DateTime<Utc>,
+    pub sold_out: Option<DateTime<Utc>>,
+    /// When it ended without selling out, as its purchase window passed or it was left out
+    pub ended: Option<DateTime<Utc>>,
+}
+
+/// Quantity of a listing observed in a channel, zero once it sold out
+#[derive(Debug, Clone)]
+pub struct QuantityChange {
+    pub item_id: String,
+    /// Missing for changes recorded before stores were told apart by id
+    pub store_id: Option<String>,
+    pub store_name: String,
+    pub store_time_zone: Tz,
+    pub price: ItemPrice,
+    pub quantity: usize,
+    pub observed_at: DateTime<Utc>,
+    /// Zero quantity of an appearance which ended without selling out
+    pub ended: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct TGTGListing {
     pub item: Item,
     pub store: Store,
@@ -87,41 +316,55 @@ pub struct TGTGListing {
     pub purchase_end: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Item {
     pub item_id: String,
     pub price_including_taxes: ItemPrice,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ItemPrice {
     pub code: String,
     pub minor_units: u32,
     pub decimals: u32,
 }
 
-#[derive(Debug, Deserialize)]
+impl ItemPrice {
+    /// Price in major units, e.g. 3.99 for 399 minor units with 2 decimals
+    pub fn amount(&self) -> f64 {
+        self.minor_units as f64 / 10u32.pow(self.decimals) as f64
+    }
+}
+
+impl fmt::Display for ItemPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} {}", self.amount(), self.code)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Store {
+    pub store_id: String,
     pub store_name: String,
     pub logo_picture: Logo,
     pub store_time_zone: Tz,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Logo {
     pub current_url: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PickupInterval {
     pub start: DateTime<Utc>,
     pub end: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PickupLocation {
     pub location: Location,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Location {
     pub latitude: f64,
     pub longitude: f64,
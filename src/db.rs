@@ -4,65 +4,294 @@ use std::{
 };
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use poise::ChoiceParameter as _;
 use poise::serenity_prelude as serenity;
-use regex::Regex;
-use serenity::model::id::ChannelId;
-use sqlx::SqlitePool;
+use serenity::model::id::{ChannelId, MessageId, RoleId, UserId};
+use sqlx::{FromRow, SqlitePool};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::SqliteConnectOptions;
+use tracing::warn;
 
-use crate::data::TGTGConfig;
+use crate::data::{
+    DigestSort, ItemMessage, ItemPrice, ListingEvent, ListingRecord, MAX_INTERVAL, MIN_INTERVAL,
+    PickupInterval, PriceFilter, QuantityChange, StoreFilter, Subscription, TGTGConfig,
+    TGTGListing, Watch,
+};
+use crate::filter::Filter;
+use crate::tgtg::Credentials;
+
+/// Migrations of the `migrations` directory, embedded in the binary
+static MIGRATOR: Migrator = sqlx::migrate!();
 
 pub struct BotDB {
     pool: SqlitePool,
 }
 
+#[derive(FromRow)]
+struct ChannelRow {
+    channel_id: String,
+    latitude: f64,
+    longitude: f64,
+    radius: i64,
+    filter: Option<String>,
+    active: bool,
+    poll_interval: i64,
+    schedule: Option<String>,
+    predictions: bool,
+    min_price: Option<f64>,
+    max_price: Option<f64>,
+    price_currency: Option<String>,
+    pickup: Option<String>,
+    mention_role: Option<String>,
+    notify: String,
+    digest: Option<String>,
+    threads: bool,
+}
+
+#[derive(FromRow)]
+struct WatchRow {
+    channel_id: String,
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    radius: i64,
+    filter: Option<String>,
+}
+
+#[derive(FromRow)]
+struct SubscriptionRow {
+    user_id: String,
+    filter: Option<String>,
+}
+
+#[derive(FromRow)]
+struct StoreFilterRow {
+    channel_id: String,
+    store_id: String,
+    store_name: String,
+    allowed: bool,
+}
+
+#[derive(FromRow)]
+struct CredentialsRow {
+    access_token: String,
+    refresh_token: String,
+    user_id: String,
+    cookie: String,
+}
+
+#[derive(FromRow)]
+struct ListingRecordRow {
+    store_name: String,
+    display_name: String,
+    price_code: String,
+    price_minor_units: i64,
+    price_decimals: i64,
+    quantity: i64,
+    pickup_start: Option<i64>,
+    pickup_end: Option<i64>,
+    first_seen: i64,
+    sold_out: Option<i64>,
+    ended: Option<i64>,
+}
+
+impl From<ListingRecordRow> for ListingRecord {
+    fn from(r: ListingRecordRow) -> Self {
+        let timestamp = |t: i64| DateTime::from_timestamp(t, 0).expect("Invalid timestamp");
+        ListingRecord {
+            store_name: r.store_name,
+            display_name: r.display_name,
+            price: ItemPrice {
+                code: r.price_code,
+                minor_units: r.price_minor_units as u32,
+                decimals: r.price_decimals as u32,
+            },
+            quantity: r.quantity as usize,
+            pickup_interval: r
+                .pickup_start
+                .zip(r.pickup_end)
+                .map(|(start, end)| PickupInterval {
+                    start: timestamp(start),
+                    end: timestamp(end),
+                }),
+            first_seen: timestamp(r.first_seen),
+            sold_out: r.sold_out.map(timestamp),
+            ended: r.ended.map(timestamp),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct QuantityChangeRow {
+    item_id: String,
+    store_id: Option<String>,
+    store_name: String,
+    store_time_zone: String,
+    price_code: String,
+    price_minor_units: i64,
+    price_decimals: i64,
+    quantity: i64,
+    observed_at: i64,
+    ended: bool,
+}
+
+impl From<QuantityChangeRow> for QuantityChange {
+    fn from(r: QuantityChangeRow) -> Self {
+        QuantityChange {
+            item_id: r.item_id,
+            store_id: r.store_id,
+            store_name: r.store_name,
+            store_time_zone: r.store_time_zone.parse().expect("Invalid time zone"),
+            price: ItemPrice {
+                code: r.price_code,
+                minor_units: r.price_minor_units as u32,
+                decimals: r.price_decimals as u32,
+            },
+            quantity: r.quantity as usize,
+            observed_at: DateTime::from_timestamp(r.observed_at, 0).expect("Invalid timestamp"),
+            ended: r.ended,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct OpenListingRow {
+    item_id: String,
+    quantity: i64,
+}
+
+#[derive(FromRow)]
+struct ItemMessageRow {
+    item_id: String,
+    message_id: String,
+    quantity: i64,
+    thread_id: Option<String>,
+}
+
+/// Parses a stored filter, which the parser of a newer version might reject
+fn parse_filter(filter: Option<&str>) -> Result<Option<Filter>> {
+    filter
+        .map(|filter_str| {
+            filter_str
+                .parse()
+                .map_err(|why| anyhow::anyhow!("invalid filter {:?}: {}", filter_str, why))
+        })
+        .transpose()
+}
+
+/// `LIKE` pattern matching names which contain `part`, taking its wildcards literally
+fn contains_pattern(part: &str) -> String {
+    let escaped = part
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
 impl BotDB {
+    /// Opens the database, creating it if missing, and upgrades its schema.
     pub async fn new(db_url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(db_url)?.create_if_missing(true);
         let token_db = BotDB {
-            pool: SqlitePool::connect(db_url).await?,
+            pool: SqlitePool::connect_with(options).await?,
         };
+        token_db.migrate().await?;
         Ok(token_db)
     }
 
+    /// Applies the migrations missing from the database, returns the schema version.
+    pub async fn migrate(&self) -> Result<i64> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default())
+    }
+
+    /// Fresh database living in memory with every migration applied.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self> {
+        // Every connection would open its own empty in-memory database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(BotDB { pool })
+    }
+
     pub async fn set_location(&self, channel_id: ChannelId, config: &TGTGConfig) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
         let channel_id_str = channel_id.to_string();
-        let optional_rec = sqlx::query!(
+        let optional_active = sqlx::query_scalar::<_, i64>(
             r#"
                 SELECT active FROM channels WHERE channel_id = ?1
             "#,
-            channel_id_str,
         )
+        .bind(&channel_id_str)
         .fetch_optional(&mut *conn)
         .await?;
-        let regex_str = config.regex.as_ref().map(|r| r.as_str());
-        match optional_rec {
-            Some(r) => {
-                sqlx::query!(
+        let filter_str = config.filter.as_ref().map(|f| f.to_string());
+        let interval = config.interval as i64;
+        let schedule_str = config.schedule.as_ref().map(|s| s.to_string());
+        let price = config.price.as_ref();
+        let pickup_str = config.pickup.as_ref().map(|p| p.to_string());
+        let mention_role_str = config.mention_role.map(|r| r.to_string());
+        let notify_str = config
+            .notify
+            .iter()
+            .map(|e| e.name())
+            .collect::<Vec<_>>()
+            .join(",");
+        let digest_str = config.digest.map(|d| d.name());
+        match optional_active {
+            Some(active) => {
+                sqlx::query(
                     r#"
-                        UPDATE channels SET latitude = ?1, longitude = ?2, radius = ?3, regex = ?4, active = ?5 WHERE channel_id = ?6
+                        UPDATE channels SET latitude = ?1, longitude = ?2, radius = ?3, filter = ?4, active = ?5, poll_interval = ?6, schedule = ?7, predictions = ?8, min_price = ?9, max_price = ?10, price_currency = ?11, pickup = ?12, mention_role = ?13, notify = ?14, digest = ?15, threads = ?16 WHERE channel_id = ?17
                     "#,
-                    config.latitude,
-                    config.longitude,
-                    config.radius,
-                    regex_str,
-                    r.active,
-                    channel_id_str,
                 )
+                .bind(config.latitude)
+                .bind(config.longitude)
+                .bind(config.radius)
+                .bind(filter_str)
+                .bind(active)
+                .bind(interval)
+                .bind(schedule_str)
+                .bind(config.predictions)
+                .bind(price.and_then(|p| p.min))
+                .bind(price.and_then(|p| p.max))
+                .bind(price.and_then(|p| p.currency.as_deref()))
+                .bind(pickup_str)
+                .bind(mention_role_str)
+                .bind(notify_str)
+                .bind(digest_str)
+                .bind(config.threads)
+                .bind(channel_id_str)
                 .execute(&mut *conn)
                 .await?;
             }
             None => {
-                sqlx::query!(
+                sqlx::query(
                     r#"
-                        INSERT INTO channels (channel_id, latitude, longitude, radius, regex, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                        INSERT INTO channels (channel_id, latitude, longitude, radius, filter, active, poll_interval, schedule, predictions, min_price, max_price, price_currency, pickup, mention_role, notify, digest, threads) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
                     "#,
-                    channel_id_str,
-                    config.latitude,
-                    config.longitude,
-                    config.radius,
-                    regex_str,
-                    0,
                 )
+                .bind(channel_id_str)
+                .bind(config.latitude)
+                .bind(config.longitude)
+                .bind(config.radius)
+                .bind(filter_str)
+                .bind(0)
+                .bind(interval)
+                .bind(schedule_str)
+                .bind(config.predictions)
+                .bind(price.and_then(|p| p.min))
+                .bind(price.and_then(|p| p.max))
+                .bind(price.and_then(|p| p.currency.as_deref()))
+                .bind(pickup_str)
+                .bind(mention_role_str)
+                .bind(notify_str)
+                .bind(digest_str)
+                .bind(config.threads)
                 .execute(&mut *conn)
                 .await?;
             }
@@ -73,25 +302,15 @@ impl BotDB {
     pub async fn change_active(&self, channel_id: ChannelId, active: bool) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
         let channel_id_str = channel_id.to_string();
-        let optional_rec = sqlx::query!(
+        sqlx::query(
             r#"
-                SELECT active FROM channels WHERE channel_id = ?1
+                UPDATE channels SET active = ?1 WHERE channel_id = ?2
             "#,
-            channel_id_str,
         )
-        .fetch_optional(&mut *conn)
+        .bind(active)
+        .bind(channel_id_str)
+        .execute(&mut *conn)
         .await?;
-        if optional_rec.is_some() {
-            sqlx::query!(
-                r#"
-                    UPDATE channels SET active = ?1 WHERE channel_id = ?2
-                "#,
-                active,
-                channel_id_str,
-            )
-            .execute(&mut *conn)
-            .await?;
-        }
         Ok(())
     }
 
@@ -99,29 +318,110 @@ impl BotDB {
         &self,
     ) -> Result<(HashMap<ChannelId, TGTGConfig>, HashSet<ChannelId>)> {
         let mut conn = self.pool.acquire().await?;
-        let records = sqlx::query!(
+        let records = sqlx::query_as::<_, ChannelRow>(
+            r#"
+                SELECT channel_id, latitude, longitude, radius, filter, active, poll_interval, schedule, predictions, min_price, max_price, price_currency, pickup, mention_role, notify, digest, threads FROM channels
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let watch_records = sqlx::query_as::<_, WatchRow>(
+            r#"
+                SELECT channel_id, name, latitude, longitude, radius, filter FROM watches ORDER BY name
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let store_records = sqlx::query_as::<_, StoreFilterRow>(
             r#"
-                SELECT channel_id, latitude, longitude, radius, regex, active FROM channels
-            "#
+                SELECT channel_id, store_id, store_name, allowed FROM store_filters ORDER BY store_name
+            "#,
         )
         .fetch_all(&mut *conn)
         .await?;
-        let location_map = records
+        let mut location_map: HashMap<ChannelId, TGTGConfig> = records
             .iter()
-            .map(|r| {
+            .filter_map(|r| {
                 let channel_id = ChannelId::from_str(&r.channel_id).expect("Invalid channel id");
                 let mut config =
                     TGTGConfig::new_with_radius(r.latitude, r.longitude, r.radius as u8);
-                if let Some(regex_str) = &r.regex {
-                    config.regex = Some(Regex::new(regex_str).expect("Invalid regex"));
+                // Skipped rather than shown unfiltered, setting the location again fixes it
+                config.filter = match parse_filter(r.filter.as_deref()) {
+                    Ok(filter) => filter,
+                    Err(why) => {
+                        warn!("Channel {}: Skipping the channel with {}", channel_id, why);
+                        return None;
+                    }
+                };
+                config.interval = (r.poll_interval.max(0) as u64).clamp(MIN_INTERVAL, MAX_INTERVAL);
+                if let Some(schedule_str) = &r.schedule {
+                    config.schedule = Some(schedule_str.parse().expect("Invalid schedule"));
+                }
+                config.predictions = r.predictions;
+                if r.min_price.is_some() || r.max_price.is_some() || r.price_currency.is_some() {
+                    config.price = Some(PriceFilter {
+                        min: r.min_price,
+                        max: r.max_price,
+                        currency: r.price_currency.clone(),
+                    });
                 }
-                (channel_id, config)
+                if let Some(pickup_str) = &r.pickup {
+                    config.pickup = Some(pickup_str.parse().expect("Invalid pickup filter"));
+                }
+                config.mention_role = r
+                    .mention_role
+                    .as_ref()
+                    .map(|role_str| RoleId::from_str(role_str).expect("Invalid role id"));
+                config.notify = r
+                    .notify
+                    .split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(|name| ListingEvent::from_name(name).expect("Invalid listing event"))
+                    .collect();
+                config.digest = r
+                    .digest
+                    .as_deref()
+                    .map(|name| DigestSort::from_name(name).expect("Invalid digest sort"));
+                config.threads = r.threads;
+                Some((channel_id, config))
             })
             .collect();
+        for r in watch_records {
+            let channel_id = ChannelId::from_str(&r.channel_id).expect("Invalid channel id");
+            let filter = match parse_filter(r.filter.as_deref()) {
+                Ok(filter) => filter,
+                Err(why) => {
+                    warn!(
+                        "Channel {}: Skipping watch {} with {}",
+                        channel_id, r.name, why
+                    );
+                    continue;
+                }
+            };
+            if let Some(config) = location_map.get_mut(&channel_id) {
+                config.watches.push(Watch {
+                    name: r.name,
+                    latitude: r.latitude,
+                    longitude: r.longitude,
+                    radius: r.radius as u8,
+                    filter,
+                });
+            }
+        }
+        for r in store_records {
+            let channel_id = ChannelId::from_str(&r.channel_id).expect("Invalid channel id");
+            if let Some(config) = location_map.get_mut(&channel_id) {
+                config.stores.push(StoreFilter {
+                    store_id: r.store_id,
+                    store_name: r.store_name,
+                    allowed: r.allowed,
+                });
+            }
+        }
         let active_set = records
             .iter()
             .filter_map(|r| {
-                if r.active == 1 {
+                if r.active {
                     let channel_id =
                         ChannelId::from_str(&r.channel_id).expect("Invalid channel id");
                     Some(channel_id)
@@ -132,4 +432,1137 @@ impl BotDB {
             .collect();
         Ok((location_map, active_set))
     }
+
+    /// Adds the watch to the channel, replacing the one with the same name.
+    pub async fn set_watch(&self, channel_id: ChannelId, watch: &Watch) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let filter_str = watch.filter.as_ref().map(|f| f.to_string());
+        sqlx::query(
+            r#"
+                INSERT INTO watches (channel_id, name, latitude, longitude, radius, filter) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (channel_id, name) DO UPDATE SET latitude = ?3, longitude = ?4, radius = ?5, filter = ?6
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(&watch.name)
+        .bind(watch.latitude)
+        .bind(watch.longitude)
+        .bind(watch.radius)
+        .bind(filter_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_watch(&self, channel_id: ChannelId, name: &str) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                DELETE FROM watches WHERE channel_id = ?1 AND name = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(name)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Allows or blocks the store in the channel, replacing its previous filter.
+    pub async fn set_store_filter(
+        &self,
+        channel_id: ChannelId,
+        filter: &StoreFilter,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO store_filters (channel_id, store_id, store_name, allowed) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (channel_id, store_id) DO UPDATE SET store_name = ?3, allowed = ?4
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(&filter.store_id)
+        .bind(&filter.store_name)
+        .bind(filter.allowed)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_store_filter(&self, channel_id: ChannelId, store_id: &str) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                DELETE FROM store_filters WHERE channel_id = ?1 AND store_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(store_id)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Id and name of the stores which had listings in the channel, most recently seen
+    /// first. The name filter works like the one of `get_history`.
+    pub async fn get_seen_stores(
+        &self,
+        channel_id: ChannelId,
+        store: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<(String, String)>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let stores = sqlx::query_as::<_, (String, String)>(
+            r#"
+                SELECT store_id, store_name FROM listing_history
+                WHERE channel_id = ?1 AND store_id IS NOT NULL AND (?2 IS NULL OR store_name LIKE ?2 ESCAPE '\')
+                GROUP BY store_id ORDER BY MAX(first_seen) DESC LIMIT ?3
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(store.map(contains_pattern))
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(stores)
+    }
+
+    pub async fn set_subscription(
+        &self,
+        channel_id: ChannelId,
+        subscription: &Subscription,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let user_id_str = subscription.user_id.to_string();
+        let filter_str = subscription.filter.as_ref().map(|f| f.to_string());
+        sqlx::query(
+            r#"
+                INSERT INTO subscriptions (channel_id, user_id, filter) VALUES (?1, ?2, ?3)
+                ON CONFLICT (channel_id, user_id) DO UPDATE SET filter = ?3
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(user_id_str)
+        .bind(filter_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes the subscription of the user, returns whether there was one
+    pub async fn remove_subscription(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let user_id_str = user_id.to_string();
+        let result = sqlx::query(
+            r#"
+                DELETE FROM subscriptions WHERE channel_id = ?1 AND user_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(user_id_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_subscriptions(&self, channel_id: ChannelId) -> Result<Vec<Subscription>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_as::<_, SubscriptionRow>(
+            r#"
+                SELECT user_id, filter FROM subscriptions WHERE channel_id = ?1
+            "#,
+        )
+        .bind(channel_id_str)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records
+            .into_iter()
+            .filter_map(|r| {
+                let user_id = UserId::from_str(&r.user_id).expect("Invalid user id");
+                match parse_filter(r.filter.as_deref()) {
+                    Ok(filter) => Some(Subscription { user_id, filter }),
+                    Err(why) => {
+                        warn!(
+                            "Channel {}: Skipping the subscription of {} with {}",
+                            channel_id, user_id, why
+                        );
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    /// Last time the channel notified about the item, by mention or DM
+    pub async fn get_notified(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let notified_at = sqlx::query_scalar::<_, i64>(
+            r#"
+                SELECT notified_at FROM notifications WHERE channel_id = ?1 AND item_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(notified_at.map(|at| DateTime::from_timestamp(at, 0).expect("Invalid timestamp")))
+    }
+
+    pub async fn set_notified(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO notifications (channel_id, item_id, notified_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT (channel_id, item_id) DO UPDATE SET notified_at = ?3
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .bind(at.timestamp())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_credentials(&self) -> Result<Option<Credentials>> {
+        let mut conn = self.pool.acquire().await?;
+        let optional_rec = sqlx::query_as::<_, CredentialsRow>(
+            r#"
+                SELECT access_token, refresh_token, user_id, cookie FROM credentials WHERE id = 1
+            "#,
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(optional_rec.map(|r| Credentials {
+            access_token: r.access_token,
+            refresh_token: r.refresh_token,
+            user_id: r.user_id,
+            cookie: r.cookie,
+        }))
+    }
+
+    pub async fn set_credentials(&self, credentials: &Credentials) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(
+            r#"
+                INSERT INTO credentials (id, access_token, refresh_token, user_id, cookie) VALUES (1, ?1, ?2, ?3, ?4)
+                ON CONFLICT (id) DO UPDATE SET access_token = ?1, refresh_token = ?2, user_id = ?3, cookie = ?4
+            "#,
+        )
+        .bind(&credentials.access_token)
+        .bind(&credentials.refresh_token)
+        .bind(&credentials.user_id)
+        .bind(&credentials.cookie)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_item_messages(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<HashMap<String, ItemMessage>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_as::<_, ItemMessageRow>(
+            r#"
+                SELECT item_id, message_id, quantity, thread_id FROM item_messages WHERE channel_id = ?1
+            "#,
+        )
+        .bind(channel_id_str)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records
+            .into_iter()
+            .map(|r| {
+                let message_id = MessageId::from_str(&r.message_id).expect("Invalid message id");
+                (
+                    r.item_id,
+                    ItemMessage {
+                        message_id,
+                        quantity: r.quantity as usize,
+                        thread_id: r.thread_id.map(|thread_id_str| {
+                            ChannelId::from_str(&thread_id_str).expect("Invalid thread id")
+                        }),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    pub async fn set_item_message(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+        item_message: &ItemMessage,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let message_id_str = item_message.message_id.to_string();
+        let quantity = item_message.quantity as i64;
+        let thread_id_str = item_message.thread_id.map(|t| t.to_string());
+        sqlx::query(
+            r#"
+                INSERT INTO item_messages (channel_id, item_id, message_id, quantity, thread_id) VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (channel_id, item_id) DO UPDATE SET message_id = ?3, quantity = ?4, thread_id = ?5
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .bind(message_id_str)
+        .bind(quantity)
+        .bind(thread_id_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_item_message(&self, channel_id: ChannelId, item_id: &str) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                DELETE FROM item_messages WHERE channel_id = ?1 AND item_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_item_messages(&self, channel_id: ChannelId) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                DELETE FROM item_messages WHERE channel_id = ?1
+            "#,
+        )
+        .bind(channel_id_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Thread of the store under the channel, if the store was seen in threads mode
+    pub async fn get_store_thread(
+        &self,
+        channel_id: ChannelId,
+        store_id: &str,
+    ) -> Result<Option<ChannelId>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let thread_id = sqlx::query_scalar::<_, String>(
+            r#"
+                SELECT thread_id FROM store_threads WHERE channel_id = ?1 AND store_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(store_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(thread_id.map(|t| ChannelId::from_str(&t).expect("Invalid thread id")))
+    }
+
+    pub async fn set_store_thread(
+        &self,
+        channel_id: ChannelId,
+        store_id: &str,
+        thread_id: ChannelId,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let thread_id_str = thread_id.to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO store_threads (channel_id, store_id, thread_id) VALUES (?1, ?2, ?3)
+                ON CONFLICT (channel_id, store_id) DO UPDATE SET thread_id = ?3
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(store_id)
+        .bind(thread_id_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Summary messages of the channel in digest mode, by page
+    pub async fn get_digest_messages(&self, channel_id: ChannelId) -> Result<Vec<MessageId>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_scalar::<_, String>(
+            r#"
+                SELECT message_id FROM digest_messages WHERE channel_id = ?1 ORDER BY page
+            "#,
+        )
+        .bind(channel_id_str)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records
+            .iter()
+            .map(|r| MessageId::from_str(r).expect("Invalid message id"))
+            .collect())
+    }
+
+    pub async fn set_digest_message(
+        &self,
+        channel_id: ChannelId,
+        page: usize,
+        message_id: MessageId,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let message_id_str = message_id.to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO digest_messages (channel_id, page, message_id) VALUES (?1, ?2, ?3)
+                ON CONFLICT (channel_id, page) DO UPDATE SET message_id = ?3
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(page as i64)
+        .bind(message_id_str)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Forgets the summary messages of the channel from the given page on
+    pub async fn delete_digest_messages(
+        &self,
+        channel_id: ChannelId,
+        from_page: usize,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                DELETE FROM digest_messages WHERE channel_id = ?1 AND page >= ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(from_page as i64)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a listing seen available, updating its open appearance if there is one.
+    pub async fn record_listing(
+        &self,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        seen_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let quantity = listing.items_available as i64;
+        let updated = sqlx::query(
+            r#"
+                UPDATE listing_history SET quantity = MAX(quantity, ?3) WHERE channel_id = ?1 AND item_id = ?2 AND sold_out IS NULL AND ended IS NULL
+            "#,
+        )
+        .bind(&channel_id_str)
+        .bind(&listing.item.item_id)
+        .bind(quantity)
+        .execute(&mut *conn)
+        .await?;
+        if updated.rows_affected() == 0 {
+            let price = &listing.item.price_including_taxes;
+            let pickup_interval = listing.pickup_interval.as_ref();
+            sqlx::query(
+                r#"
+                    INSERT INTO listing_history (channel_id, item_id, store_name, display_name, price_code, price_minor_units, price_decimals, quantity, pickup_start, pickup_end, first_seen, store_id)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                "#,
+            )
+            .bind(channel_id_str)
+            .bind(&listing.item.item_id)
+            .bind(&listing.store.store_name)
+            .bind(&listing.display_name)
+            .bind(&price.code)
+            .bind(price.minor_units)
+            .bind(price.decimals)
+            .bind(quantity)
+            .bind(pickup_interval.map(|p| p.start.timestamp()))
+            .bind(pickup_interval.map(|p| p.end.timestamp()))
+            .bind(seen_at.timestamp())
+            .bind(&listing.store.store_id)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// When the listing last sold out in the channel, if it ever did
+    pub async fn get_last_sold_out(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let sold_out = sqlx::query_scalar::<_, Option<i64>>(
+            r#"
+                SELECT MAX(sold_out) FROM listing_history WHERE channel_id = ?1 AND item_id = ?2
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        Ok(sold_out.map(|at| DateTime::from_timestamp(at, 0).expect("Invalid timestamp")))
+    }
+
+    /// Listings of the channel which haven't sold out or ended yet, with their last recorded
+    /// quantity.
+    pub async fn get_open_quantities(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<HashMap<String, usize>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_as::<_, OpenListingRow>(
+            r#"
+                SELECT h.item_id, COALESCE((
+                    SELECT q.quantity FROM quantity_changes q WHERE q.channel_id = h.channel_id AND q.item_id = h.item_id
+                    ORDER BY q.observed_at DESC, q.id DESC LIMIT 1
+                ), h.quantity) AS quantity
+                FROM listing_history h WHERE h.channel_id = ?1 AND h.sold_out IS NULL AND h.ended IS NULL
+            "#,
+        )
+        .bind(channel_id_str)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records
+            .into_iter()
+            .map(|r| (r.item_id, r.quantity as usize))
+            .collect())
+    }
+
+    /// Closes the open appearance of the listing, as sold out or as ended without selling out
+    /// when its purchase window passed or it was left out of the channel.
+    pub async fn close_listing(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+        closed_at: DateTime<Utc>,
+        sold_out: bool,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                UPDATE listing_history SET sold_out = CASE WHEN ?4 THEN ?3 END, ended = CASE WHEN ?4 THEN NULL ELSE ?3 END
+                WHERE channel_id = ?1 AND item_id = ?2 AND sold_out IS NULL AND ended IS NULL
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .bind(closed_at.timestamp())
+        .bind(sold_out)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Listings first seen in the channel since the given time, newest first. The store
+    /// name filter is case insensitive and matches parts of the name literally.
+    pub async fn get_history(
+        &self,
+        channel_id: ChannelId,
+        since: DateTime<Utc>,
+        store: Option<&str>,
+    ) -> Result<Vec<ListingRecord>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_as::<_, ListingRecordRow>(
+            r#"
+                SELECT store_name, display_name, price_code, price_minor_units, price_decimals, quantity, pickup_start, pickup_end, first_seen, sold_out, ended
+                FROM listing_history WHERE channel_id = ?1 AND first_seen >= ?2 AND (?3 IS NULL OR store_name LIKE ?3 ESCAPE '\')
+                ORDER BY first_seen DESC
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(since.timestamp())
+        .bind(store.map(contains_pattern))
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records.into_iter().map(ListingRecord::from).collect())
+    }
+
+    /// Records the quantity of a listing seen available.
+    pub async fn record_quantity(
+        &self,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        observed_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let price = &listing.item.price_including_taxes;
+        sqlx::query(
+            r#"
+                INSERT INTO quantity_changes (channel_id, item_id, store_name, store_time_zone, price_code, price_minor_units, price_decimals, quantity, observed_at, store_id)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(&listing.item.item_id)
+        .bind(&listing.store.store_name)
+        .bind(listing.store.store_time_zone.name())
+        .bind(&price.code)
+        .bind(price.minor_units)
+        .bind(price.decimals)
+        .bind(listing.items_available as i64)
+        .bind(observed_at.timestamp())
+        .bind(&listing.store.store_id)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Records that a listing sold out or ended, with the details of its last recorded quantity.
+    pub async fn record_closed(
+        &self,
+        channel_id: ChannelId,
+        item_id: &str,
+        observed_at: DateTime<Utc>,
+        sold_out: bool,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        sqlx::query(
+            r#"
+                INSERT INTO quantity_changes (channel_id, item_id, store_name, store_time_zone, price_code, price_minor_units, price_decimals, quantity, observed_at, ended, store_id)
+                SELECT channel_id, item_id, store_name, store_time_zone, price_code, price_minor_units, price_decimals, 0, ?3, NOT ?4, store_id
+                FROM quantity_changes WHERE channel_id = ?1 AND item_id = ?2 ORDER BY observed_at DESC, id DESC LIMIT 1
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(item_id)
+        .bind(observed_at.timestamp())
+        .bind(sold_out)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Quantity changes observed in the channel since the given time, oldest first. The
+    /// store name filter works like the one of `get_history`.
+    pub async fn get_quantity_changes(
+        &self,
+        channel_id: ChannelId,
+        since: DateTime<Utc>,
+        store: Option<&str>,
+    ) -> Result<Vec<QuantityChange>> {
+        let mut conn = self.pool.acquire().await?;
+        let channel_id_str = channel_id.to_string();
+        let records = sqlx::query_as::<_, QuantityChangeRow>(
+            r#"
+                SELECT item_id, store_id, store_name, store_time_zone, price_code, price_minor_units, price_decimals, quantity, observed_at, ended
+                FROM quantity_changes WHERE channel_id = ?1 AND observed_at >= ?2 AND (?3 IS NULL OR store_name LIKE ?3 ESCAPE '\')
+                ORDER BY observed_at, id
+            "#,
+        )
+        .bind(channel_id_str)
+        .bind(since.timestamp())
+        .bind(store.map(contains_pattern))
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(records.into_iter().map(QuantityChange::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::{FakeSource, ListingSource};
+
+    fn channel() -> ChannelId {
+        ChannelId::new(42)
+    }
+
+    #[tokio::test]
+    async fn test_locations() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new_full(52.3676, 4.9041, 3, r#"store ~ "bakery""#.parse()?);
+        config.interval = 120;
+        config.schedule = Some("08:00-22:00 mon-fri Europe/Amsterdam".parse()?);
+        config.predictions = true;
+        config.price = Some(PriceFilter {
+            min: None,
+            max: Some(4.5),
+            currency: Some("EUR".to_owned()),
+        });
+        config.pickup = Some("17:00-21:00 today".parse()?);
+        config.mention_role = Some(RoleId::new(99));
+        config.notify = vec![ListingEvent::Restock, ListingEvent::QuantityDrop];
+        config.digest = Some(DigestSort::Price);
+        config.threads = true;
+        bot_db.set_location(channel(), &config).await?;
+        bot_db.set_location(ChannelId::new(7), &TGTGConfig::new(1.0, 2.0)).await?;
+        bot_db.change_active(channel(), true).await?;
+
+        // Changing the location keeps the channel active
+        config.radius = 5;
+        bot_db.set_location(channel(), &config).await?;
+
+        let (location_map, active_set) = bot_db.get_locations().await?;
+        assert_eq!(location_map.len(), 2);
+        assert_eq!(active_set, HashSet::from([channel()]));
+        let stored = &location_map[&channel()];
+        assert_eq!((stored.latitude, stored.longitude), (52.3676, 4.9041));
+        assert_eq!(stored.radius, 5);
+        assert_eq!(
+            stored.filter.as_ref().map(|f| f.to_string()),
+            Some(r#"store ~ "bakery""#.to_owned())
+        );
+        assert_eq!(stored.interval, 120);
+        assert_eq!(stored.schedule, config.schedule);
+        assert!(stored.predictions);
+        assert!(!location_map[&ChannelId::new(7)].predictions);
+        assert_eq!(stored.price, config.price);
+        assert_eq!(location_map[&ChannelId::new(7)].price, None);
+        assert_eq!(stored.pickup, config.pickup);
+        assert_eq!(stored.mention_role, config.mention_role);
+        assert_eq!(stored.notify, config.notify);
+        assert_eq!(
+            location_map[&ChannelId::new(7)].notify,
+            vec![ListingEvent::New, ListingEvent::Restock]
+        );
+        assert_eq!(stored.digest, Some(DigestSort::Price));
+        assert_eq!(location_map[&ChannelId::new(7)].digest, None);
+        assert!(stored.threads);
+        assert!(!location_map[&ChannelId::new(7)].threads);
+
+        // A currency alone filters the listings too
+        let mut other = location_map[&ChannelId::new(7)].clone();
+        other.price = Some(PriceFilter {
+            min: None,
+            max: None,
+            currency: Some("EUR".to_owned()),
+        });
+        bot_db.set_location(ChannelId::new(7), &other).await?;
+        let (location_map, _) = bot_db.get_locations().await?;
+        let price = location_map[&ChannelId::new(7)].price.as_ref();
+        assert_eq!(price.map(|p| p.to_string()), Some("in EUR".to_owned()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_filters_are_skipped() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.watches.push(Watch {
+            name: "office".to_owned(),
+            latitude: 52.3551,
+            longitude: 4.9553,
+            radius: 2,
+            filter: None,
+        });
+        bot_db.set_location(channel(), &config).await?;
+        bot_db.set_watch(channel(), &config.watches[0]).await?;
+        bot_db
+            .set_location(ChannelId::new(7), &TGTGConfig::new(1.0, 2.0))
+            .await?;
+        // Stored by a version with another filter syntax
+        sqlx::query("UPDATE channels SET filter = 'bakery' WHERE channel_id = '7'")
+            .execute(&bot_db.pool)
+            .await?;
+        sqlx::query("UPDATE watches SET filter = 'bakery'")
+            .execute(&bot_db.pool)
+            .await?;
+
+        let (location_map, _) = bot_db.get_locations().await?;
+        assert_eq!(location_map.len(), 1);
+        assert!(location_map[&channel()].watches.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watches() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        bot_db
+            .set_location(channel(), &TGTGConfig::new(52.3676, 4.9041))
+            .await?;
+        let mut watch = Watch {
+            name: "office".to_owned(),
+            latitude: 52.3551,
+            longitude: 4.8921,
+            radius: 1,
+            filter: None,
+        };
+        bot_db.set_watch(channel(), &watch).await?;
+        watch.radius = 2;
+        bot_db.set_watch(channel(), &watch).await?;
+        watch.name = "home".to_owned();
+        bot_db.set_watch(channel(), &watch).await?;
+
+        let (location_map, _) = bot_db.get_locations().await?;
+        let watches = &location_map[&channel()].watches;
+        let names = watches.iter().map(|w| w.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["home", "office"]);
+        assert!(watches.iter().all(|w| w.radius == 2));
+
+        bot_db.remove_watch(channel(), "home").await?;
+        let (location_map, _) = bot_db.get_locations().await?;
+        assert_eq!(location_map[&channel()].watches.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_filters() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        bot_db
+            .set_location(channel(), &TGTGConfig::new(52.3676, 4.9041))
+            .await?;
+        let mut sushi = StoreFilter {
+            store_id: "502".to_owned(),
+            store_name: "Sushi Centraal".to_owned(),
+            allowed: true,
+        };
+        bot_db.set_store_filter(channel(), &sushi).await?;
+        sushi.allowed = false;
+        bot_db.set_store_filter(channel(), &sushi).await?;
+        let bakery = StoreFilter {
+            store_id: "501".to_owned(),
+            store_name: "Bakery Zuid".to_owned(),
+            allowed: true,
+        };
+        bot_db.set_store_filter(channel(), &bakery).await?;
+
+        let (location_map, _) = bot_db.get_locations().await?;
+        assert_eq!(location_map[&channel()].stores, vec![bakery, sushi]);
+
+        bot_db.remove_store_filter(channel(), "501").await?;
+        let (location_map, _) = bot_db.get_locations().await?;
+        assert_eq!(location_map[&channel()].stores.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let mut subscription = Subscription {
+            user_id: UserId::new(5),
+            filter: None,
+        };
+        bot_db.set_subscription(channel(), &subscription).await?;
+        subscription.filter = Some("price < 4".parse()?);
+        bot_db.set_subscription(channel(), &subscription).await?;
+        let subscriptions = bot_db.get_subscriptions(channel()).await?;
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].user_id, UserId::new(5));
+        assert_eq!(
+            subscriptions[0].filter.as_ref().map(|f| f.to_string()),
+            Some("price < 4".to_owned())
+        );
+        assert!(
+            bot_db
+                .get_subscriptions(ChannelId::new(7))
+                .await?
+                .is_empty()
+        );
+
+        assert!(
+            bot_db
+                .remove_subscription(channel(), UserId::new(5))
+                .await?
+        );
+        assert!(
+            !bot_db
+                .remove_subscription(channel(), UserId::new(5))
+                .await?
+        );
+        assert!(bot_db.get_subscriptions(channel()).await?.is_empty());
+
+        assert_eq!(bot_db.get_notified(channel(), "1001").await?, None);
+        let at = DateTime::from_timestamp(1_790_000_000, 0).unwrap();
+        bot_db.set_notified(channel(), "1001", at).await?;
+        bot_db
+            .set_notified(channel(), "1001", at + chrono::Duration::hours(1))
+            .await?;
+        assert_eq!(
+            bot_db.get_notified(channel(), "1001").await?,
+            Some(at + chrono::Duration::hours(1))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_credentials() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        assert!(bot_db.get_credentials().await?.is_none());
+        let mut credentials = Credentials {
+            access_token: "access".to_owned(),
+            refresh_token: "refresh".to_owned(),
+            user_id: "1".to_owned(),
+            cookie: "cookie".to_owned(),
+        };
+        bot_db.set_credentials(&credentials).await?;
+        credentials.access_token = "refreshed".to_owned();
+        bot_db.set_credentials(&credentials).await?;
+        assert!(bot_db.get_credentials().await? == Some(credentials));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_item_messages() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let item_message = |message_id, quantity| ItemMessage {
+            message_id: MessageId::new(message_id),
+            quantity,
+            thread_id: None,
+        };
+        bot_db
+            .set_item_message(channel(), "1001", &item_message(1, 3))
+            .await?;
+        bot_db
+            .set_item_message(channel(), "1001", &item_message(1, 2))
+            .await?;
+        let in_thread = ItemMessage {
+            thread_id: Some(ChannelId::new(50)),
+            ..item_message(2, 5)
+        };
+        bot_db
+            .set_item_message(channel(), "1002", &in_thread)
+            .await?;
+        bot_db
+            .set_item_message(ChannelId::new(7), "1001", &item_message(3, 1))
+            .await?;
+
+        let messages = bot_db.get_item_messages(channel()).await?;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages["1001"].quantity, 2);
+        assert_eq!(messages["1002"].message_id, MessageId::new(2));
+        assert_eq!(messages["1001"].thread_id, None);
+        assert_eq!(messages["1002"].thread_id, Some(ChannelId::new(50)));
+
+        bot_db.delete_item_message(channel(), "1002").await?;
+        assert_eq!(bot_db.get_item_messages(channel()).await?.len(), 1);
+        bot_db.delete_item_messages(channel()).await?;
+        assert!(bot_db.get_item_messages(channel()).await?.is_empty());
+        assert_eq!(bot_db.get_item_messages(ChannelId::new(7)).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_threads() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        assert_eq!(bot_db.get_store_thread(channel(), "501").await?, None);
+        bot_db
+            .set_store_thread(channel(), "501", ChannelId::new(50))
+            .await?;
+        bot_db
+            .set_store_thread(channel(), "501", ChannelId::new(51))
+            .await?;
+        assert_eq!(
+            bot_db.get_store_thread(channel(), "501").await?,
+            Some(ChannelId::new(51))
+        );
+        assert_eq!(
+            bot_db.get_store_thread(ChannelId::new(7), "501").await?,
+            None
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_digest_messages() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        for (page, message_id) in [(1, 12), (0, 10), (0, 11)] {
+            bot_db
+                .set_digest_message(channel(), page, MessageId::new(message_id))
+                .await?;
+        }
+        assert_eq!(
+            bot_db.get_digest_messages(channel()).await?,
+            vec![MessageId::new(11), MessageId::new(12)]
+        );
+        bot_db.delete_digest_messages(channel(), 1).await?;
+        assert_eq!(
+            bot_db.get_digest_messages(channel()).await?,
+            vec![MessageId::new(11)]
+        );
+        bot_db.delete_digest_messages(channel(), 0).await?;
+        assert!(bot_db.get_digest_messages(channel()).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_listing_history() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_quantity_changed"])?;
+        let appears = source.get_items(&config).await?.remove(0);
+        let changed = source.get_items(&config).await?.remove(0);
+        let at = |hour| {
+            DateTime::parse_from_rfc3339(&format!("2026-10-01T{:02}:00:00Z", hour))
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+
+        bot_db.record_listing(channel(), &changed, at(8)).await?;
+        bot_db.record_listing(channel(), &appears, at(9)).await?;
+        assert_eq!(bot_db.get_last_sold_out(channel(), "1001").await?, None);
+        bot_db
+            .close_listing(channel(), "1001", at(10), true)
+            .await?;
+        assert_eq!(
+            bot_db.get_last_sold_out(channel(), "1001").await?,
+            Some(at(10))
+        );
+        // Back in stock later on, a new appearance which ends without selling out
+        bot_db.record_listing(channel(), &changed, at(18)).await?;
+        bot_db
+            .close_listing(channel(), "1001", at(20), false)
+            .await?;
+        assert_eq!(
+            bot_db.get_last_sold_out(channel(), "1001").await?,
+            Some(at(10))
+        );
+
+        let history = bot_db.get_history(channel(), at(0), None).await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].first_seen, at(18));
+        assert_eq!((history[0].quantity, history[0].sold_out), (1, None));
+        assert_eq!(history[0].ended, Some(at(20)));
+        assert_eq!(history[1].first_seen, at(8));
+        assert_eq!(
+            (history[1].quantity, history[1].sold_out),
+            (3, Some(at(10)))
+        );
+        assert_eq!(history[1].price.to_string(), "3.99 EUR");
+        assert!(history[1].pickup_interval.is_some());
+
+        assert_eq!(bot_db.get_history(channel(), at(12), None).await?.len(), 1);
+        let bakery = bot_db.get_history(channel(), at(0), Some("bakery")).await?;
+        assert_eq!(bakery.len(), 2);
+        let sushi = bot_db.get_history(channel(), at(0), Some("sushi")).await?;
+        assert!(sushi.is_empty());
+        // Wildcards typed by users are matched literally
+        assert!(
+            bot_db
+                .get_history(channel(), at(0), Some("%"))
+                .await?
+                .is_empty()
+        );
+        assert!(
+            bot_db
+                .get_history(channel(), at(0), Some("Bakery_Zuid"))
+                .await?
+                .is_empty()
+        );
+
+        let stores = bot_db.get_seen_stores(channel(), None, 25).await?;
+        assert_eq!(stores, vec![("501".to_owned(), "Bakery Zuid".to_owned())]);
+        assert!(
+            bot_db
+                .get_seen_stores(channel(), Some("sushi"), 25)
+                .await?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quantity_changes() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_quantity_changed"])?;
+        let appears = source.get_items(&config).await?.remove(0);
+        let changed = source.get_items(&config).await?.remove(0);
+        let at = |hour| {
+            DateTime::parse_from_rfc3339(&format!("2026-10-01T{:02}:00:00Z", hour))
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+
+        bot_db.record_quantity(channel(), &appears, at(8)).await?;
+        bot_db.record_quantity(channel(), &changed, at(9)).await?;
+        bot_db
+            .record_closed(channel(), "1001", at(10), true)
+            .await?;
+        // Nothing to copy from for an unknown item
+        bot_db
+            .record_closed(channel(), "2002", at(10), true)
+            .await?;
+
+        let changes = bot_db.get_quantity_changes(channel(), at(0), None).await?;
+        let quantities = changes
+            .iter()
+            .map(|c| (c.quantity, c.observed_at))
+            .collect::<Vec<_>>();
+        assert_eq!(quantities, vec![(3, at(8)), (1, at(9)), (0, at(10))]);
+        assert!(!changes[2].ended);
+        assert_eq!(changes[2].store_name, changes[0].store_name);
+        assert_eq!(changes[2].store_id, Some(appears.store.store_id.clone()));
+        assert_eq!(changes[2].price.to_string(), "3.99 EUR");
+
+        let since = bot_db.get_quantity_changes(channel(), at(9), None).await?;
+        assert_eq!(since.len(), 2);
+
+        // Ending without selling out is told apart
+        bot_db.record_quantity(channel(), &changed, at(12)).await?;
+        bot_db
+            .record_closed(channel(), "1001", at(14), false)
+            .await?;
+        let changes = bot_db.get_quantity_changes(channel(), at(14), None).await?;
+        assert_eq!((changes[0].quantity, changes[0].ended), (0, true));
+        let sushi = bot_db
+            .get_quantity_changes(channel(), at(0), Some("sushi"))
+            .await?;
+        assert!(sushi.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_quantities() -> Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_quantity_changed"])?;
+        let appears = source.get_items(&config).await?.remove(0);
+        let changed = source.get_items(&config).await?.remove(0);
+        let now = Utc::now();
+
+        bot_db.record_listing(channel(), &appears, now).await?;
+        bot_db.record_quantity(channel(), &appears, now).await?;
+        bot_db.record_listing(channel(), &changed, now).await?;
+        bot_db.record_quantity(channel(), &changed, now).await?;
+        // The last quantity counts, not the highest one of the appearance
+        let open = bot_db.get_open_quantities(channel()).await?;
+        assert_eq!(open, HashMap::from([("1001".to_owned(), 1)]));
+
+        bot_db.close_listing(channel(), "1001", now, true).await?;
+        assert!(bot_db.get_open_quantities(channel()).await?.is_empty());
+        Ok(())
+    }
 }
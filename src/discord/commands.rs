@@ -1,12 +1,24 @@
+use std::sync::Arc;
+
 use anyhow::Context as _;
+use chrono::Utc;
 use poise::serenity_prelude::{self as serenity};
+use poise::ChoiceParameter as _;
 
-use regex::Regex;
-use serenity::all::{CreateEmbed, CreateMessage};
-use tracing::info;
+use serenity::all::{CreateEmbed, CreateEmbedFooter, CreateMessage};
+use tracing::{info, warn};
 
-use crate::data::{TGTGConfig, OSM_ZOOM_LEVEL, RADIUS_UNIT};
+use crate::data::{
+    DigestSort, ListingEvent, PriceFilter, StoreFilter, Subscription, TGTGConfig, Watch,
+    DEFAULT_RADIUS, MAX_INTERVAL, MIN_INTERVAL, OSM_ZOOM_LEVEL, RADIUS_UNIT,
+};
+use crate::filter::Filter;
+use crate::pickup::{self, PickupDay, PickupFilter};
+use crate::schedule::Schedule;
+use crate::stats::store_stats;
+use crate::tgtg::EmailLogin;
 
+use super::sink::DiscordSink;
 use super::{Context, Error};
 
 /// Check the bot if it's ready to work
@@ -55,6 +67,9 @@ pub async fn default(
 
     let bot_db = &ctx.data().bot_db;
     bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data()
+        .scheduler
+        .reconfigure(ctx.channel_id(), location.clone());
     info!(
         "Channel {}: Location set ({}, {})",
         ctx.channel_id(),
@@ -75,8 +90,8 @@ pub async fn default(
             format!("{} {}", location.radius, RADIUS_UNIT),
             true,
         );
-    if let Some(regex) = &location.regex {
-        embed = embed.field("Regex", regex.as_str().replace('*', "\\*"), true);
+    if let Some(filter) = &location.filter {
+        embed = embed.field("Filter", filter.to_string().replace('*', "\\*"), true);
     }
     ctx.reply("Location has been set!").await?;
     ctx.channel_id()
@@ -115,6 +130,9 @@ async fn radius(
 
     let bot_db = &ctx.data().bot_db;
     bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data()
+        .scheduler
+        .reconfigure(ctx.channel_id(), location.clone());
     info!("Channel {}: Radius set {} ", ctx.channel_id(), radius);
     let mut embed = CreateEmbed::new()
         .title("Radius")
@@ -126,8 +144,8 @@ async fn radius(
             "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
             OSM_ZOOM_LEVEL, location.latitude, location.longitude
         ));
-    if let Some(regex) = &location.regex {
-        embed = embed.field("Regex", regex.as_str().replace('*', "\\*"), true);
+    if let Some(filter) = &location.filter {
+        embed = embed.field("Filter", filter.to_string().replace('*', "\\*"), true);
     }
     ctx.reply("Location has been set!").await?;
     ctx.channel_id()
@@ -137,15 +155,18 @@ async fn radius(
     Ok(())
 }
 
-/// Sets the location with given radius with regex filter
+/// Sets the location with given radius with a filter like store ~ "bakery" and price < 5
 #[poise::command[prefix_command, slash_command]]
 async fn full(
     ctx: Context<'_>,
     #[description = "latitude"] latitude: f64,
     #[description = "longitude"] longitude: f64,
     #[description = "radius"] radius: u8,
-    #[description = "regex"] regex: String,
+    #[description = "filter like store ~ \"bakery\" and price < 5"] filter: String,
 ) -> Result<(), Error> {
+    let Some(filter) = parse_filter(ctx, &filter).await? else {
+        return Ok(());
+    };
     let location_map = &ctx.data().tgtg_configs;
     let location = {
         let exists = location_map.read().await.contains_key(&ctx.channel_id());
@@ -155,11 +176,10 @@ async fn full(
             location.latitude = latitude;
             location.longitude = longitude;
             location.radius = radius;
-            location.regex = Some(Regex::new(&regex)?);
+            location.filter = Some(filter);
             location.clone()
         } else {
-            let regex = Regex::new(&regex)?;
-            let location = TGTGConfig::new_full(latitude, longitude, radius, regex);
+            let location = TGTGConfig::new_full(latitude, longitude, radius, filter);
             location_map
                 .write()
                 .await
@@ -170,6 +190,9 @@ async fn full(
 
     let bot_db = &ctx.data().bot_db;
     bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data()
+        .scheduler
+        .reconfigure(ctx.channel_id(), location.clone());
     info!("Channel {}: Radius set {} ", ctx.channel_id(), radius);
     let mut embed = CreateEmbed::new()
         .title("Radius")
@@ -181,8 +204,8 @@ async fn full(
             "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
             OSM_ZOOM_LEVEL, location.latitude, location.longitude
         ));
-    if let Some(regex) = &location.regex {
-        embed = embed.field("Regex", regex.as_str().replace('*', "\\*"), true);
+    if let Some(filter) = &location.filter {
+        embed = embed.field("Filter", filter.to_string().replace('*', "\\*"), true);
     }
     ctx.reply("Location has been set!").await?;
     ctx.channel_id()
@@ -192,6 +215,1072 @@ async fn full(
     Ok(())
 }
 
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("watch_add", "watch_list", "watch_remove")
+)]
+pub async fn watch(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Hello there!").await?;
+    Ok(())
+}
+
+/// Watches another named location in the channel, next to its main location
+#[poise::command(prefix_command, slash_command, rename = "add")]
+async fn watch_add(
+    ctx: Context<'_>,
+    #[description = "name of the watch"] name: String,
+    #[description = "latitude"] latitude: f64,
+    #[description = "longitude"] longitude: f64,
+    #[description = "radius (default 1 km)"] radius: Option<u8>,
+    #[description = "filter like store ~ \"bakery\" and price < 5"] filter: Option<String>,
+) -> Result<(), Error> {
+    let filter = match filter {
+        Some(filter) => match parse_filter(ctx, &filter).await? {
+            Some(filter) => Some(filter),
+            None => return Ok(()),
+        },
+        None => None,
+    };
+    let watch = Watch {
+        name,
+        latitude,
+        longitude,
+        radius: radius.unwrap_or(DEFAULT_RADIUS),
+        filter,
+    };
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.watches.retain(|w| w.name != watch.name);
+                location.watches.push(watch.clone());
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_watch(ctx.channel_id(), &watch).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!("Channel {}: Watch {} added", ctx.channel_id(), watch.name);
+    let mut embed = CreateEmbed::new()
+        .title(format!("Watch {}", watch.name))
+        .description("TooGoodToGo location is watched in this channel")
+        .url(format!(
+            "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
+            OSM_ZOOM_LEVEL, latitude, longitude
+        ))
+        .field("Latitude", format!("{:.4}", latitude), true)
+        .field("Longitude", format!("{:.4}", longitude), true)
+        .field("Radius", format!("{} {}", watch.radius, RADIUS_UNIT), true);
+    if let Some(filter) = &watch.filter {
+        embed = embed.field("Filter", filter.to_string().replace('*', "\\*"), true);
+    }
+    ctx.reply("Watch has been added!").await?;
+    ctx.channel_id()
+        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
+        .await?;
+    Ok(())
+}
+
+/// Lists the locations watched in the channel
+#[poise::command(prefix_command, slash_command, rename = "list")]
+async fn watch_list(ctx: Context<'_>) -> Result<(), Error> {
+    let location_map = ctx.data().tgtg_configs.read().await;
+    let Some(location) = location_map.get(&ctx.channel_id()) else {
+        ctx.reply("Location is not found!").await?;
+        return Ok(());
+    };
+    let mut embed = CreateEmbed::new()
+        .title("Watches")
+        .description("TooGoodToGo locations watched in this channel")
+        .field(
+            "main",
+            format!(
+                "({:.4}, {:.4}) {} {}",
+                location.latitude, location.longitude, location.radius, RADIUS_UNIT
+            ),
+            false,
+        );
+    for watch in location.watches.iter() {
+        let mut value = format!(
+            "({:.4}, {:.4}) {} {}",
+            watch.latitude, watch.longitude, watch.radius, RADIUS_UNIT
+        );
+        if let Some(filter) = &watch.filter {
+            value += &format!(" filtered by {}", filter.to_string().replace('*', "\\*"));
+        }
+        embed = embed.field(&watch.name, value, false);
+    }
+    ctx.channel_id()
+        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
+        .await?;
+    ctx.reply("Here are the watches!").await?;
+    Ok(())
+}
+
+/// Stops watching the named location in the channel
+#[poise::command(prefix_command, slash_command, rename = "remove")]
+async fn watch_remove(
+    ctx: Context<'_>,
+    #[description = "name of the watch"] name: String,
+) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) if location.watches.iter().any(|w| w.name == name) => {
+                location.watches.retain(|w| w.name != name);
+                location.clone()
+            }
+            _ => {
+                ctx.reply("Watch is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.remove_watch(ctx.channel_id(), &name).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!("Channel {}: Watch {} removed", ctx.channel_id(), name);
+    ctx.reply("Watch has been removed!").await?;
+    Ok(())
+}
+
+/// Sets how often the bot checks TGTG for the channel in seconds
+#[poise::command[prefix_command, slash_command]]
+pub async fn interval(
+    ctx: Context<'_>,
+    #[description = "seconds between checks"] seconds: u64,
+) -> Result<(), Error> {
+    if seconds < MIN_INTERVAL {
+        ctx.reply(format!("Interval can't be shorter than {} seconds!", MIN_INTERVAL))
+            .await?;
+        return Ok(());
+    }
+    if seconds > MAX_INTERVAL {
+        ctx.reply(format!("Interval can't be longer than {} seconds!", MAX_INTERVAL))
+            .await?;
+        return Ok(());
+    }
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.interval = seconds;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!("Channel {}: Interval set {} s", ctx.channel_id(), seconds);
+    ctx.reply(format!("Checking TGTG every {} seconds!", seconds)).await?;
+    Ok(())
+}
+
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("filter_set", "filter_clear", "filter_price", "filter_pickup")
+)]
+pub async fn filter(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Hello there!").await?;
+    Ok(())
+}
+
+/// Only shows listings matching the filter, like store ~ "bakery" and price < 5
+#[poise::command(prefix_command, slash_command, rename = "set")]
+async fn filter_set(
+    ctx: Context<'_>,
+    #[description = "filter like store ~ \"bakery\" and price < 5"] filter: String,
+) -> Result<(), Error> {
+    let Some(filter) = parse_filter(ctx, &filter).await? else {
+        return Ok(());
+    };
+    set_filter(ctx, Some(filter)).await
+}
+
+/// Shows every listing of the channel's main location again
+#[poise::command(prefix_command, slash_command, rename = "clear")]
+async fn filter_clear(ctx: Context<'_>) -> Result<(), Error> {
+    set_filter(ctx, None).await
+}
+
+/// Parses a filter expression, replying with the reason when it's not valid
+async fn parse_filter(ctx: Context<'_>, filter: &str) -> Result<Option<Filter>, Error> {
+    match filter.parse::<Filter>() {
+        Ok(filter) => Ok(Some(filter)),
+        Err(why) => {
+            ctx.reply(format!("Filter is not valid: {}", why)).await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn set_filter(ctx: Context<'_>, filter: Option<Filter>) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.filter = filter;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data()
+        .scheduler
+        .reconfigure(ctx.channel_id(), location.clone());
+    match &location.filter {
+        Some(filter) => {
+            info!("Channel {}: Filter set {}", ctx.channel_id(), filter);
+            ctx.reply(format!("Only showing listings where {}!", filter))
+                .await?;
+        }
+        None => {
+            info!("Channel {}: Filter cleared", ctx.channel_id());
+            ctx.reply("Filter has been cleared!").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Only shows listings within the price range or currency, without either the filter is removed
+#[poise::command(prefix_command, slash_command, rename = "price")]
+async fn filter_price(
+    ctx: Context<'_>,
+    #[description = "lowest price, like 2.50"] min: Option<f64>,
+    #[description = "highest price, like 5"] max: Option<f64>,
+    #[description = "currency code, like EUR"] currency: Option<String>,
+) -> Result<(), Error> {
+    // Prefix commands take any float, like NaN or inf
+    if !min.into_iter().chain(max).all(f64::is_finite)
+        || min.is_some_and(|min| min < 0.0)
+        || min.zip(max).is_some_and(|(min, max)| min > max)
+    {
+        ctx.reply("Price range is not valid!").await?;
+        return Ok(());
+    }
+    let price = (min.is_some() || max.is_some() || currency.is_some()).then(|| PriceFilter {
+        min,
+        max,
+        currency: currency.map(|code| code.to_uppercase()),
+    });
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.price = price.clone();
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    match price {
+        Some(price) => {
+            info!("Channel {}: Price filter set {}", ctx.channel_id(), price);
+            ctx.reply(format!("Only showing listings priced {}!", price))
+                .await?;
+        }
+        None => {
+            info!("Channel {}: Price filter removed", ctx.channel_id());
+            ctx.reply("Price filter has been removed!").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Only shows pickups during the hours or on the day, without either the filter is removed
+#[poise::command(prefix_command, slash_command, rename = "pickup")]
+async fn filter_pickup(
+    ctx: Context<'_>,
+    #[description = "hours like 17:00-21:00"] hours: Option<String>,
+    #[description = "day of the pickup"] day: Option<PickupDay>,
+) -> Result<(), Error> {
+    let hours = match hours.as_deref().map(pickup::parse_hours).transpose() {
+        Ok(hours) => hours,
+        Err(why) => {
+            ctx.reply(format!("Pickup hours are not valid: {}", why))
+                .await?;
+            return Ok(());
+        }
+    };
+    let pickup = (hours.is_some() || day.is_some()).then_some(PickupFilter { hours, day });
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.pickup = pickup.clone();
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    match pickup {
+        Some(pickup) => {
+            info!("Channel {}: Pickup filter set {}", ctx.channel_id(), pickup);
+            ctx.reply(format!("Only showing pickups {}!", pickup)).await?;
+        }
+        None => {
+            info!("Channel {}: Pickup filter removed", ctx.channel_id());
+            ctx.reply("Pickup filter has been removed!").await?;
+        }
+    }
+    Ok(())
+}
+
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("store_allow", "store_block", "store_remove", "store_list")
+)]
+pub async fn store(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Hello there!").await?;
+    Ok(())
+}
+
+/// Most stores offered while typing a store, discord doesn't show more
+const STORE_CHOICES: u32 = 25;
+
+/// Offers the stores seen in the channel, most recent first
+async fn autocomplete_seen_store(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let bot_db = &ctx.data().bot_db;
+    match bot_db
+        .get_seen_stores(ctx.channel_id(), Some(partial), STORE_CHOICES)
+        .await
+    {
+        Ok(stores) => stores
+            .into_iter()
+            .map(|(store_id, store_name)| serenity::AutocompleteChoice::new(store_name, store_id))
+            .collect(),
+        Err(why) => {
+            warn!("Channel {}: Failed to load stores with {}", ctx.channel_id(), why);
+            Vec::new()
+        }
+    }
+}
+
+/// Offers the stores allowed or blocked in the channel
+async fn autocomplete_filtered_store(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let location_map = ctx.data().tgtg_configs.read().await;
+    let partial = partial.to_lowercase();
+    location_map
+        .get(&ctx.channel_id())
+        .map(|location| {
+            location
+                .stores
+                .iter()
+                .filter(|s| s.store_name.to_lowercase().contains(&partial))
+                .map(|s| serenity::AutocompleteChoice::new(&s.store_name, s.store_id.as_str()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Only shows the allowed stores in the channel, along with the other allowed ones
+#[poise::command(prefix_command, slash_command, rename = "allow")]
+async fn store_allow(
+    ctx: Context<'_>,
+    #[description = "store seen in the channel"]
+    #[autocomplete = "autocomplete_seen_store"]
+    store: String,
+) -> Result<(), Error> {
+    set_store_filter(ctx, store, true).await
+}
+
+/// Never shows the store in the channel
+#[poise::command(prefix_command, slash_command, rename = "block")]
+async fn store_block(
+    ctx: Context<'_>,
+    #[description = "store seen in the channel"]
+    #[autocomplete = "autocomplete_seen_store"]
+    store: String,
+) -> Result<(), Error> {
+    set_store_filter(ctx, store, false).await
+}
+
+/// Takes the store id, or the name for prefix commands, of a store seen in the channel
+async fn set_store_filter(ctx: Context<'_>, store: String, allowed: bool) -> Result<(), Error> {
+    let bot_db = &ctx.data().bot_db;
+    let seen = bot_db
+        .get_seen_stores(ctx.channel_id(), None, u32::MAX)
+        .await?;
+    let Some((store_id, store_name)) = seen
+        .into_iter()
+        .find(|(id, name)| *id == store || name.eq_ignore_ascii_case(&store))
+    else {
+        ctx.reply("Store is not found!").await?;
+        return Ok(());
+    };
+    let filter = StoreFilter {
+        store_id,
+        store_name,
+        allowed,
+    };
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.stores.retain(|s| s.store_id != filter.store_id);
+                location.stores.push(filter.clone());
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    bot_db.set_store_filter(ctx.channel_id(), &filter).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    let verb = if allowed { "allowed" } else { "blocked" };
+    info!(
+        "Channel {}: Store {} {}",
+        ctx.channel_id(),
+        filter.store_id,
+        verb
+    );
+    ctx.reply(format!("{} is {}!", filter.store_name, verb))
+        .await?;
+    Ok(())
+}
+
+/// Neither allows nor blocks the store anymore
+#[poise::command(prefix_command, slash_command, rename = "remove")]
+async fn store_remove(
+    ctx: Context<'_>,
+    #[description = "allowed or blocked store"]
+    #[autocomplete = "autocomplete_filtered_store"]
+    store: String,
+) -> Result<(), Error> {
+    let (location, filter) = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        let found = location_map.get_mut(&ctx.channel_id()).and_then(|location| {
+            let index = location.stores.iter().position(|s| {
+                s.store_id == store || s.store_name.eq_ignore_ascii_case(&store)
+            })?;
+            let filter = location.stores.remove(index);
+            Some((location.clone(), filter))
+        });
+        match found {
+            Some(found) => found,
+            None => {
+                ctx.reply("Store is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db
+        .remove_store_filter(ctx.channel_id(), &filter.store_id)
+        .await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!(
+        "Channel {}: Store {} removed",
+        ctx.channel_id(),
+        filter.store_id
+    );
+    ctx.reply(format!("{} is no longer filtered!", filter.store_name))
+        .await?;
+    Ok(())
+}
+
+/// Lists the stores allowed and blocked in the channel
+#[poise::command(prefix_command, slash_command, rename = "list")]
+async fn store_list(ctx: Context<'_>) -> Result<(), Error> {
+    let location_map = ctx.data().tgtg_configs.read().await;
+    let Some(location) = location_map.get(&ctx.channel_id()) else {
+        ctx.reply("Location is not found!").await?;
+        return Ok(());
+    };
+    if location.stores.is_empty() {
+        ctx.reply("No stores are allowed or blocked!").await?;
+        return Ok(());
+    }
+    let names = |allowed| {
+        location
+            .stores
+            .iter()
+            .filter(|s| s.allowed == allowed)
+            .map(|s| s.store_name.as_str())
+            .collect::<Vec<_>>()
+    };
+    let mut embed = CreateEmbed::new()
+        .title("Stores")
+        .description("TooGoodToGo stores filtered in this channel");
+    for (title, allowed) in [("Allowed", true), ("Blocked", false)] {
+        let names = names(allowed);
+        if !names.is_empty() {
+            embed = embed.field(title, names.join("\n"), true);
+        }
+    }
+    ctx.channel_id()
+        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
+        .await?;
+    ctx.reply("Here are the stores!").await?;
+    Ok(())
+}
+
+/// Posts a heads-up before the usual release time of the stores seen in the channel
+#[poise::command[prefix_command, slash_command]]
+pub async fn predictions(
+    ctx: Context<'_>,
+    #[description = "whether heads-ups are posted"] enabled: bool,
+) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.predictions = enabled;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!("Channel {}: Predictions set {}", ctx.channel_id(), enabled);
+    if enabled {
+        ctx.reply("Heads-ups will be posted before the usual release times!")
+            .await?;
+    } else {
+        ctx.reply("Heads-ups are turned off!").await?;
+    }
+    Ok(())
+}
+
+/// Told when the channel's mention or notified events don't apply because of its digest
+const DIGEST_NOTE: &str = "The digest doesn't mention the role or reply about events, \
+    only subscribers get a DM for new listings and restocks.";
+
+/// Whether the digest of the channel leaves out its mention or some notified events
+fn digest_ignores(location: &TGTGConfig) -> bool {
+    location.digest.is_some()
+        && (location.mention_role.is_some()
+            || location
+                .notify
+                .iter()
+                .any(|e| !matches!(e, ListingEvent::New | ListingEvent::Restock)))
+}
+
+/// Shows the available listings in pinned summary messages instead of one message each
+#[poise::command[prefix_command, slash_command]]
+pub async fn digest(
+    ctx: Context<'_>,
+    #[description = "whether the digest replaces the listing messages"] enabled: bool,
+    #[description = "order of the listings (default distance)"] sort: Option<DigestSort>,
+) -> Result<(), Error> {
+    let digest = enabled.then(|| sort.unwrap_or(DigestSort::Distance));
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.digest = digest;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let ignores = digest_ignores(&location);
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    match digest {
+        Some(sort) => {
+            info!("Channel {}: Digest set by {}", ctx.channel_id(), sort.name());
+            let mut reply = format!(
+                "Listings will be summarised by {} on the next check!",
+                sort.name()
+            );
+            if ignores {
+                reply = format!("{} {}", reply, DIGEST_NOTE);
+            }
+            ctx.reply(reply).await?;
+        }
+        None => {
+            info!("Channel {}: Digest removed", ctx.channel_id());
+            ctx.reply("Listings will be posted one by one on the next check!")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Posts the listings of each store in its own thread under the channel
+#[poise::command(prefix_command, slash_command, guild_only)]
+pub async fn threads(
+    ctx: Context<'_>,
+    #[description = "whether each store gets a thread"] enabled: bool,
+) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.threads = enabled;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!("Channel {}: Threads set {}", ctx.channel_id(), enabled);
+    if enabled {
+        ctx.reply("New listings will be posted in a thread per store!")
+            .await?;
+    } else {
+        ctx.reply("New listings will be posted in the channel!").await?;
+    }
+    Ok(())
+}
+
+/// Chooses whether a listing event posts a notification or only edits the listing message
+#[poise::command[prefix_command, slash_command]]
+pub async fn notify(
+    ctx: Context<'_>,
+    #[description = "listing event"] event: ListingEvent,
+    #[description = "whether the event posts a notification"] enabled: bool,
+) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.notify.retain(|e| *e != event);
+                if enabled {
+                    location.notify.push(event);
+                }
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let ignores = digest_ignores(&location);
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    info!(
+        "Channel {}: Notify {} set {}",
+        ctx.channel_id(),
+        event.name(),
+        enabled
+    );
+    if enabled {
+        let mut reply = format!("Listings will notify about {}!", event.name());
+        if ignores {
+            reply = format!("{} {}", reply, DIGEST_NOTE);
+        }
+        ctx.reply(reply).await?;
+    } else {
+        ctx.reply(format!("Listings won't notify about {}!", event.name()))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Mentions the role on new listings of the channel, without a role the mention is removed
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn mention(
+    ctx: Context<'_>,
+    #[description = "role to mention"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let role_id = role.as_ref().map(|r| r.id);
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.mention_role = role_id;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let ignores = digest_ignores(&location);
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data().scheduler.reconfigure(ctx.channel_id(), location);
+    match role {
+        Some(role) => {
+            info!("Channel {}: Mention set {}", ctx.channel_id(), role.id);
+            let mut reply = format!("New listings will mention {}!", role.name);
+            if ignores {
+                reply = format!("{} {}", reply, DIGEST_NOTE);
+            }
+            ctx.reply(reply).await?;
+        }
+        None => {
+            info!("Channel {}: Mention removed", ctx.channel_id());
+            ctx.reply("New listings won't mention anyone!").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Get a DM when a listing appears in the channel, optionally only matching a filter
+#[poise::command[prefix_command, slash_command]]
+pub async fn subscribe(
+    ctx: Context<'_>,
+    #[description = "filter like store ~ \"bakery\" and price < 5"] filter: Option<String>,
+) -> Result<(), Error> {
+    let filter = match filter {
+        Some(filter) => match parse_filter(ctx, &filter).await? {
+            Some(filter) => Some(filter),
+            None => return Ok(()),
+        },
+        None => None,
+    };
+    if !ctx
+        .data()
+        .tgtg_configs
+        .read()
+        .await
+        .contains_key(&ctx.channel_id())
+    {
+        ctx.reply("Location is not found!").await?;
+        return Ok(());
+    }
+    let subscription = Subscription {
+        user_id: ctx.author().id,
+        filter,
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db
+        .set_subscription(ctx.channel_id(), &subscription)
+        .await?;
+    info!(
+        "Channel {}: User {} subscribed",
+        ctx.channel_id(),
+        subscription.user_id
+    );
+    match &subscription.filter {
+        Some(filter) => {
+            ctx.reply(format!("You will get a DM for new listings where {}!", filter))
+                .await?;
+        }
+        None => {
+            ctx.reply("You will get a DM for new listings!").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Stop getting DMs for the listings of the channel
+#[poise::command[prefix_command, slash_command]]
+pub async fn unsubscribe(ctx: Context<'_>) -> Result<(), Error> {
+    let bot_db = &ctx.data().bot_db;
+    if bot_db
+        .remove_subscription(ctx.channel_id(), ctx.author().id)
+        .await?
+    {
+        info!(
+            "Channel {}: User {} unsubscribed",
+            ctx.channel_id(),
+            ctx.author().id
+        );
+        ctx.reply("You won't get DMs anymore!").await?;
+    } else {
+        ctx.reply("You are not subscribed!").await?;
+    }
+    Ok(())
+}
+
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("schedule_set", "schedule_clear")
+)]
+pub async fn schedule(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Hello there!").await?;
+    Ok(())
+}
+
+/// Only checks TGTG for the channel during the given hours and weekdays
+#[poise::command(prefix_command, slash_command, rename = "set")]
+async fn schedule_set(
+    ctx: Context<'_>,
+    #[description = "hours like 08:00-22:00"] hours: String,
+    #[description = "weekdays like mon-fri,sun or daily"] days: String,
+    #[description = "time zone like Europe/Amsterdam (default UTC)"] timezone: Option<String>,
+) -> Result<(), Error> {
+    let schedule = format!(
+        "{} {} {}",
+        hours,
+        days,
+        timezone.as_deref().unwrap_or("UTC")
+    );
+    let schedule = match schedule.parse::<Schedule>() {
+        Ok(schedule) => schedule,
+        Err(why) => {
+            ctx.reply(format!("Schedule is not valid: {}", why)).await?;
+            return Ok(());
+        }
+    };
+    set_schedule(ctx, Some(schedule)).await
+}
+
+/// Checks TGTG for the channel around the clock again
+#[poise::command(prefix_command, slash_command, rename = "clear")]
+async fn schedule_clear(ctx: Context<'_>) -> Result<(), Error> {
+    set_schedule(ctx, None).await
+}
+
+async fn set_schedule(ctx: Context<'_>, schedule: Option<Schedule>) -> Result<(), Error> {
+    let location = {
+        let mut location_map = ctx.data().tgtg_configs.write().await;
+        match location_map.get_mut(&ctx.channel_id()) {
+            Some(location) => {
+                location.schedule = schedule;
+                location.clone()
+            }
+            None => {
+                ctx.reply("Location is not found!").await?;
+                return Ok(());
+            }
+        }
+    };
+    let bot_db = &ctx.data().bot_db;
+    bot_db.set_location(ctx.channel_id(), &location).await?;
+    ctx.data()
+        .scheduler
+        .reconfigure(ctx.channel_id(), location.clone());
+    match &location.schedule {
+        Some(schedule) => {
+            info!("Channel {}: Schedule set {}", ctx.channel_id(), schedule);
+            ctx.reply(format!("Checking TGTG during {}!", schedule)).await?;
+        }
+        None => {
+            info!("Channel {}: Schedule cleared", ctx.channel_id());
+            ctx.reply("Checking TGTG around the clock!").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Most listings shown by /history, older ones are left out
+const HISTORY_LIMIT: usize = 20;
+
+/// Longest period in days /history and /stats look back on
+const MAX_DAYS: u32 = 365;
+
+/// Most characters discord allows in an embed description
+const DESCRIPTION_LIMIT: usize = 4096;
+
+/// Shows the listings which appeared in the channel over the last days
+#[poise::command[prefix_command, slash_command]]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "number of days (default 7)"] days: Option<u32>,
+    #[description = "part of the store name"] store: Option<String>,
+) -> Result<(), Error> {
+    let days = days.unwrap_or(7);
+    if days > MAX_DAYS {
+        ctx.reply(format!("History can't go back more than {} days!", MAX_DAYS))
+            .await?;
+        return Ok(());
+    }
+    let since = Utc::now() - chrono::Duration::days(days as i64);
+    let bot_db = &ctx.data().bot_db;
+    let history = bot_db
+        .get_history(ctx.channel_id(), since, store.as_deref())
+        .await?;
+    info!(
+        "Channel {}: History of {} days with {} listings",
+        ctx.channel_id(),
+        days,
+        history.len()
+    );
+    if history.is_empty() {
+        ctx.reply("Nothing appeared in that period!").await?;
+        return Ok(());
+    }
+    let lines = history
+        .iter()
+        .take(HISTORY_LIMIT)
+        .map(|r| {
+            let sold_out = match (r.sold_out, r.ended) {
+                (Some(sold_out), _) => format!(
+                    "sold out after {} min",
+                    (sold_out - r.first_seen).num_minutes()
+                ),
+                (None, Some(ended)) => format!(
+                    "gone after {} min without selling out",
+                    (ended - r.first_seen).num_minutes()
+                ),
+                (None, None) => "still available".to_string(),
+            };
+            let pickup = match &r.pickup_interval {
+                Some(interval) => format!(
+                    ", pickup <t:{}:t> - <t:{}:t>",
+                    interval.start.timestamp(),
+                    interval.end.timestamp()
+                ),
+                None => String::new(),
+            };
+            format!(
+                "<t:{}:f> **{}** {}, {}x {}{}, {}",
+                r.first_seen.timestamp(),
+                r.store_name,
+                r.display_name,
+                r.quantity,
+                r.price,
+                pickup,
+                sold_out
+            )
+        })
+        .collect::<Vec<_>>();
+    // Leave out the oldest listings which don't fit in the description
+    let mut description = String::new();
+    let mut shown = 0;
+    for line in lines {
+        let length = description.chars().count() + line.chars().count() + 1;
+        if shown > 0 && length > DESCRIPTION_LIMIT {
+            break;
+        }
+        if shown > 0 {
+            description.push('\n');
+        }
+        description.extend(line.chars().take(DESCRIPTION_LIMIT));
+        shown += 1;
+    }
+    let mut embed = CreateEmbed::new().title("History").description(description);
+    if history.len() > shown {
+        embed = embed.footer(CreateEmbedFooter::new(format!(
+            "{} older listings are not shown",
+            history.len() - shown
+        )));
+    }
+    ctx.channel_id()
+        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
+        .await?;
+    ctx.reply(format!("Here's the history of the last {} days!", days))
+        .await?;
+    Ok(())
+}
+
+/// Most stores shown by /stats, discord doesn't allow more embed fields
+const STATS_LIMIT: usize = 25;
+
+/// Shows when bags usually appear per store and how fast they sell out
+#[poise::command[prefix_command, slash_command]]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "number of days (default 30)"] days: Option<u32>,
+    #[description = "part of the store name"] store: Option<String>,
+) -> Result<(), Error> {
+    let days = days.unwrap_or(30);
+    if days > MAX_DAYS {
+        ctx.reply(format!("Stats can't go back more than {} days!", MAX_DAYS))
+            .await?;
+        return Ok(());
+    }
+    let since = Utc::now() - chrono::Duration::days(days as i64);
+    let bot_db = &ctx.data().bot_db;
+    let changes = bot_db
+        .get_quantity_changes(ctx.channel_id(), since, store.as_deref())
+        .await?;
+    let stats = store_stats(&changes);
+    info!(
+        "Channel {}: Stats of {} days over {} stores",
+        ctx.channel_id(),
+        days,
+        stats.len()
+    );
+    if stats.is_empty() {
+        ctx.reply("Nothing appeared in that period!").await?;
+        return Ok(());
+    }
+    let mut embed = CreateEmbed::new().title("Stats");
+    for s in stats.iter().take(STATS_LIMIT) {
+        let release_times = s
+            .release_times
+            .iter()
+            .map(|(day, time)| format!("{} {}", day, time.format("%H:%M")))
+            .collect::<Vec<_>>();
+        let sellout = match s.median_sellout {
+            Some(duration) => format!("{} min", duration.num_minutes()),
+            None => "never seen".to_string(),
+        };
+        embed = embed.field(
+            &s.store_name,
+            format!(
+                "Releases: {}\nUsually at: {}\nSells out in: {}\nQuantity: {:.1}\nPrice: {:.2} {}",
+                s.releases,
+                release_times.join(", "),
+                sellout,
+                s.average_quantity,
+                s.average_price,
+                s.currency
+            ),
+            true,
+        );
+    }
+    if stats.len() > STATS_LIMIT {
+        embed = embed.footer(CreateEmbedFooter::new(format!(
+            "{} stores with fewer releases are not shown",
+            stats.len() - STATS_LIMIT
+        )));
+    }
+    ctx.channel_id()
+        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
+        .await?;
+    ctx.reply(format!("Here are the stats of the last {} days!", days))
+        .await?;
+    Ok(())
+}
+
 /// Check the status for the current channel
 #[poise::command[prefix_command, slash_command]]
 pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
@@ -217,8 +1306,61 @@ pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
                 "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
                 OSM_ZOOM_LEVEL, location.latitude, location.longitude
             ));
-        if let Some(regex) = &location.regex {
-            embed = embed.field("Regex", regex.as_str().replace('*', "\\*"), true);
+        if let Some(filter) = &location.filter {
+            embed = embed.field("Filter", filter.to_string().replace('*', "\\*"), true);
+        }
+        if !location.watches.is_empty() {
+            let names = location.watches.iter().map(|w| w.name.as_str());
+            embed = embed.field("Watches", names.collect::<Vec<_>>().join(", "), true);
+        }
+        embed = embed.field("Interval", format!("{} s", location.interval), true);
+        if let Some(schedule) = &location.schedule {
+            embed = embed.field("Schedule", schedule.to_string(), true);
+            let now = Utc::now();
+            let next_activation = match schedule.next_activation(now) {
+                Some(at) if at > now => format!("<t:{}:R>", at.timestamp()),
+                Some(_) => "Now".to_string(),
+                None => "Never".to_string(),
+            };
+            embed = embed.field("Next activation", next_activation, true);
+        }
+        if let Some(price) = &location.price {
+            embed = embed.field("Price", price.to_string(), true);
+        }
+        if let Some(pickup) = &location.pickup {
+            embed = embed.field("Pickup", pickup.to_string(), true);
+        }
+        if !location.stores.is_empty() {
+            let allowed = location.stores.iter().filter(|s| s.allowed).count();
+            embed = embed.field(
+                "Stores",
+                format!(
+                    "{} allowed, {} blocked",
+                    allowed,
+                    location.stores.len() - allowed
+                ),
+                true,
+            );
+        }
+        if location.predictions {
+            embed = embed.field("Predictions", "✅", true);
+        }
+        if let Some(sort) = location.digest {
+            embed = embed.field("Digest", format!("by {}", sort.name()), true);
+        }
+        if location.threads {
+            embed = embed.field("Threads", "✅", true);
+        }
+        if !location.notify.is_empty() {
+            let events = location.notify.iter().map(|e| e.name());
+            embed = embed.field("Notify", events.collect::<Vec<_>>().join(", "), true);
+        }
+        if let Some(role_id) = location.mention_role {
+            embed = embed.field("Mention", format!("<@&{}>", role_id), true);
+        }
+        let subscriptions = ctx.data().bot_db.get_subscriptions(ctx.channel_id()).await?;
+        if !subscriptions.is_empty() {
+            embed = embed.field("Subscribers", subscriptions.len().to_string(), true);
         }
         embed = embed.field("Active", if is_active { "✅" } else { "❌" }, true);
         let message = CreateMessage::new().add_embed(embed);
@@ -250,11 +1392,12 @@ pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
         let active_channels = &ctx.data().active_channels;
         let bot_db = &ctx.data().bot_db;
         bot_db.change_active(ctx.channel_id(), true).await?;
-        let http = ctx.serenity_context().http.clone();
+        let sink = Arc::new(DiscordSink::new(ctx.serenity_context().http.clone()));
         let cm = crate::monitor::ChannelMonitor::init(
-            http,
+            sink,
+            bot_db.clone(),
             ctx.channel_id(),
-            ctx.data().tgtg_bindings.clone(),
+            ctx.data().scheduler.clone(),
             tgtg_config.clone(),
         );
 
@@ -287,9 +1430,16 @@ pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     }
 
-    let active_channels = &ctx.data().active_channels;
-    let mut active_channels = active_channels.write().await;
-    active_channels.retain(|c| c.channel_id != ctx.channel_id());
+    let stopped = {
+        let mut active_channels = ctx.data().active_channels.write().await;
+        active_channels
+            .extract_if(|c| c.channel_id == ctx.channel_id())
+            .collect::<Vec<_>>()
+    };
+    // Unlike a shutdown, an explicit stop removes the listings from the channel
+    for monitor in stopped {
+        monitor.stop().await;
+    }
 
     let bot_db = &ctx.data().bot_db;
     bot_db.change_active(ctx.channel_id(), false).await?;
@@ -299,3 +1449,26 @@ pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Log in to TGTG with the email of the account shared by every server (bot owner only)
+#[poise::command(slash_command, ephemeral, owners_only)]
+pub async fn login(
+    ctx: Context<'_>,
+    #[description = "email of the TGTG account"] email: String,
+) -> Result<(), Error> {
+    info!("Channel {}: TGTG login requested", ctx.channel_id());
+    ctx.defer_ephemeral().await?;
+    let login = EmailLogin::start(&email).await?;
+    ctx.say(
+        "Check your mailbox on a PC and click the login link. \
+        The link won't work on a phone with the TGTG app installed.",
+    )
+    .await?;
+    let credentials = login.wait().await?;
+    ctx.data().bot_db.set_credentials(&credentials).await?;
+    ctx.data().tgtg_client.set_credentials(credentials).await?;
+    ctx.data().scheduler.credentials_changed().await;
+    info!("Channel {}: TGTG login completed", ctx.channel_id());
+    ctx.say("Logged in to TGTG!").await?;
+    Ok(())
+}
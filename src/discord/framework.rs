@@ -2,6 +2,7 @@ use poise::serenity_prelude as serenity;
 
 use serenity::all::GatewayIntents;
 use serenity::Client;
+use tokio::sync::oneshot;
 
 use crate::data::DiscordData;
 
@@ -10,26 +11,43 @@ pub struct DiscordClient {
 }
 
 impl DiscordClient {
+    /// Builds the client, the commands get their data once it's sent, before connecting.
+    /// That way the data can hold sinks sharing the client's http.
     pub async fn new(
         token: &str,
         intents: GatewayIntents,
-        data: DiscordData,
+        data: oneshot::Receiver<DiscordData>,
     ) -> anyhow::Result<Self> {
         let framework = poise::Framework::builder()
             .options(poise::FrameworkOptions {
                 commands: vec![
                     super::commands::health(),
                     super::commands::location(),
+                    super::commands::watch(),
+                    super::commands::interval(),
+                    super::commands::schedule(),
+                    super::commands::filter(),
+                    super::commands::store(),
+                    super::commands::predictions(),
+                    super::commands::digest(),
+                    super::commands::threads(),
+                    super::commands::notify(),
+                    super::commands::mention(),
+                    super::commands::subscribe(),
+                    super::commands::unsubscribe(),
+                    super::commands::history(),
+                    super::commands::stats(),
                     super::commands::status(),
                     super::commands::start(),
                     super::commands::stop(),
+                    super::commands::login(),
                 ],
                 ..Default::default()
             })
             .setup(|ctx, _ready, framework| {
                 Box::pin(async move {
                     poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                    Ok(data)
+                    Ok(data.await?)
                 })
             })
             .build();
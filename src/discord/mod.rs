@@ -1,5 +1,6 @@
 pub mod commands;
 pub mod framework;
+pub mod sink;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, crate::data::DiscordData, Error>;
@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use serenity::all::{
+    ChannelId, ChannelType, CreateAllowedMentions, CreateEmbed, CreateMessage, CreateThread,
+    EditMessage, Http, MessageId, RoleId, Timestamp, UserId,
+};
+use serenity::http::HttpError;
+use tracing::warn;
+
+use crate::data::{DigestPage, ListingEvent, OSM_ZOOM_LEVEL, RADIUS_UNIT, TGTGListing};
+use crate::sink::{ListingSink, SinkError};
+use crate::stats::ReleasePrediction;
+
+/// Listing sink posting embeds to discord channels through serenity.
+pub struct DiscordSink {
+    http: Arc<Http>,
+}
+
+impl DiscordSink {
+    pub fn new(http: Arc<Http>) -> Self {
+        Self { http }
+    }
+}
+
+#[async_trait]
+impl ListingSink for DiscordSink {
+    async fn post(
+        &self,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        mention: Option<RoleId>,
+    ) -> anyhow::Result<MessageId> {
+        let mut builder = CreateMessage::new().add_embed(listing_embed(listing));
+        if let Some(role_id) = mention {
+            builder = builder
+                .content(format!("<@&{}>", role_id))
+                .allowed_mentions(CreateAllowedMentions::new().roles([role_id]));
+        }
+        let msg = channel_id
+            .send_message(&self.http, builder)
+            .await
+            .map_err(sink_error)?;
+        Ok(msg.id)
+    }
+
+    async fn edit(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        listing: &TGTGListing,
+    ) -> anyhow::Result<()> {
+        let builder = EditMessage::new().embed(listing_embed(listing));
+        channel_id
+            .edit_message(&self.http, message_id, builder)
+            .await
+            .map_err(sink_error)?;
+        Ok(())
+    }
+
+    async fn reply(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        listing: &TGTGListing,
+        event: ListingEvent,
+        mention: Option<RoleId>,
+    ) -> anyhow::Result<MessageId> {
+        let mut content = event_content(listing, event);
+        let mut allowed_mentions = CreateAllowedMentions::new();
+        if let Some(role_id) = mention {
+            content = format!("<@&{}> {}", role_id, content);
+            allowed_mentions = allowed_mentions.roles([role_id]);
+        }
+        let builder = CreateMessage::new()
+            .content(content)
+            .reference_message((channel_id, message_id))
+            .allowed_mentions(allowed_mentions);
+        let msg = channel_id.send_message(&self.http, builder).await?;
+        Ok(msg.id)
+    }
+
+    async fn delete(&self, channel_id: ChannelId, message_id: MessageId) -> anyhow::Result<()> {
+        channel_id
+            .delete_message(&self.http, message_id)
+            .await
+            .map_err(sink_error)?;
+        Ok(())
+    }
+
+    async fn post_digest(
+        &self,
+        channel_id: ChannelId,
+        page: &DigestPage,
+    ) -> anyhow::Result<MessageId> {
+        let builder = CreateMessage::new().add_embed(digest_embed(page));
+        let msg = channel_id
+            .send_message(&self.http, builder)
+            .await
+            .map_err(sink_error)?;
+        // The summary still works unpinned when the bot may not manage messages
+        if let Err(why) = msg.pin(&self.http).await {
+            warn!("Channel {}: Failed to pin digest with {}", channel_id, why);
+        }
+        Ok(msg.id)
+    }
+
+    async fn edit_digest(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        page: &DigestPage,
+    ) -> anyhow::Result<()> {
+        let builder = EditMessage::new().embed(digest_embed(page));
+        channel_id
+            .edit_message(&self.http, message_id, builder)
+            .await
+            .map_err(sink_error)?;
+        Ok(())
+    }
+
+    async fn create_thread(&self, channel_id: ChannelId, name: &str) -> anyhow::Result<ChannelId> {
+        // Discord rejects thread names longer than 100 characters
+        let name = name.chars().take(100).collect::<String>();
+        let builder = CreateThread::new(name).kind(ChannelType::PublicThread);
+        let thread = channel_id.create_thread(&self.http, builder).await?;
+        Ok(thread.id)
+    }
+
+    async fn notice(&self, channel_id: ChannelId, content: &str) -> anyhow::Result<MessageId> {
+        let msg = channel_id.say(&self.http, content).await?;
+        Ok(msg.id)
+    }
+
+    async fn heads_up(
+        &self,
+        channel_id: ChannelId,
+        prediction: &ReleasePrediction,
+    ) -> anyhow::Result<MessageId> {
+        let builder = CreateMessage::new().add_embed(heads_up_embed(prediction));
+        let msg = channel_id.send_message(&self.http, builder).await?;
+        Ok(msg.id)
+    }
+
+    async fn direct_message(
+        &self,
+        user_id: UserId,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+    ) -> anyhow::Result<()> {
+        let builder = CreateMessage::new()
+            .content(format!("New listing in <#{}>", channel_id))
+            .add_embed(listing_embed(listing));
+        let dm_channel = user_id.create_dm_channel(&self.http).await?;
+        dm_channel.send_message(&self.http, builder).await?;
+        Ok(())
+    }
+}
+
+/// Error code of discord for a message which doesn't exist
+const UNKNOWN_MESSAGE: isize = 10008;
+/// Error code of discord for a channel or thread which doesn't exist
+const UNKNOWN_CHANNEL: isize = 10003;
+/// Error code of discord for an operation on an archived thread
+const ARCHIVED_THREAD: isize = 50083;
+/// Error code of discord for a locked thread
+const LOCKED_THREAD: isize = 160005;
+
+/// Turns the discord errors the monitors recover from into a `SinkError`
+fn sink_error(why: serenity::Error) -> anyhow::Error {
+    match &why {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            match response.error.code {
+                UNKNOWN_MESSAGE => SinkError::UnknownMessage.into(),
+                UNKNOWN_CHANNEL | ARCHIVED_THREAD | LOCKED_THREAD => {
+                    SinkError::UnavailableChannel.into()
+                }
+                _ => why.into(),
+            }
+        }
+        _ => why.into(),
+    }
+}
+
+/// Constructs the message embed with quantity and date of a listing
+fn listing_embed(i: &TGTGListing) -> CreateEmbed {
+    // A sold out message is only kept around for a restock to reply to
+    let (title, quantity) = if i.items_available == 0 {
+        (
+            format!("{} (sold out)", i.store.store_name),
+            "Sold out".to_owned(),
+        )
+    } else {
+        (i.store.store_name.clone(), format!("{}", i.items_available))
+    };
+    let mut embed = CreateEmbed::new()
+        .title(title)
+        .description(&i.display_name)
+        .field("Price", i.item.price_including_taxes.to_string(), true)
+        .field("Quantity", quantity, true)
+        .field(
+            "Distance",
+            format!("{:.2} {}", i.distance, RADIUS_UNIT),
+            true,
+        )
+        .image(&i.store.logo_picture.current_url)
+        .url(format!(
+            "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
+            OSM_ZOOM_LEVEL,
+            i.pickup_location.location.latitude,
+            i.pickup_location.location.longitude
+        ));
+    if let Some(interval) = &i.pickup_interval {
+        let timezone = i.store.store_time_zone;
+        embed = embed.field(
+            "Pickup interval",
+            format!(
+                "{} - {}",
+                interval
+                    .start
+                    .with_timezone(&timezone)
+                    .format("%a %H:%M %Z"),
+                interval.end.with_timezone(&timezone).format("%a %H:%M %Z")
+            ),
+            true,
+        );
+    }
+    embed
+}
+
+/// Constructs the summary embed of a digest page, a line per listing
+fn digest_embed(p: &DigestPage) -> CreateEmbed {
+    let mut title = "Available bags".to_owned();
+    if p.pages > 1 {
+        title = format!("{} ({}/{})", title, p.page + 1, p.pages);
+    }
+    let lines = p
+        .listings
+        .iter()
+        .map(|i| {
+            let mut line = format!(
+                "**{}** · {} left · {} · {:.2} {}",
+                i.display_name,
+                i.items_available,
+                i.item.price_including_taxes,
+                i.distance,
+                RADIUS_UNIT
+            );
+            if let Some(interval) = &i.pickup_interval {
+                let timezone = i.store.store_time_zone;
+                line = format!(
+                    "{} · {}-{}",
+                    line,
+                    interval.start.with_timezone(&timezone).format("%a %H:%M"),
+                    interval.end.with_timezone(&timezone).format("%H:%M")
+                );
+            }
+            line
+        })
+        .collect::<Vec<_>>();
+    let description = if lines.is_empty() {
+        "Nothing available right now".to_owned()
+    } else {
+        lines.join("\n")
+    };
+    CreateEmbed::new()
+        .title(title)
+        .description(description)
+        .timestamp(Timestamp::now())
+}
+
+/// Describes the change of a listing in a reply to its message
+fn event_content(i: &TGTGListing, event: ListingEvent) -> String {
+    match event {
+        ListingEvent::New => format!("New, {} available!", i.items_available),
+        ListingEvent::Restock => format!("Restocked, {} available!", i.items_available),
+        ListingEvent::QuantityDrop => format!("Only {} left!", i.items_available),
+        ListingEvent::SoldOut => "Sold out!".to_owned(),
+    }
+}
+
+/// Constructs the heads-up embed of a release expected soon
+fn heads_up_embed(p: &ReleasePrediction) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .title(format!("About to drop: {}", &p.store_name))
+        .description(format!(
+            "Bags usually appear around <t:{}:t> on this weekday",
+            p.expected.timestamp()
+        ))
+        .field(
+            "Expected",
+            format!("<t:{}:R>", p.expected.timestamp()),
+            true,
+        )
+        .field("Past releases", p.releases.to_string(), true);
+    if let Some(sellout) = p.median_sellout {
+        embed = embed.field(
+            "Sells out in",
+            format!("{} min", sellout.num_minutes()),
+            true,
+        );
+    }
+    embed
+}
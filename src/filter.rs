@@ -0,0 +1,586 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use chrono::NaiveTime;
+use regex::{Regex, RegexBuilder};
+
+use crate::data::TGTGListing;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Condition on listings like `store ~ "bakery" and price < 5`, combining comparisons of
+/// listing fields with `and`, `or`, `not` and parentheses.
+#[derive(Clone, Debug)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Compare(Field, Op, Value),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Name,
+    Store,
+    Price,
+    Quantity,
+    Distance,
+    PickupStart,
+    PickupEnd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Match,
+    NotMatch,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Text(String),
+    /// Case-insensitive regex of a `~` or `!~` comparison
+    Pattern(Regex),
+    Number(f64),
+    Time(NaiveTime),
+}
+
+impl Field {
+    const ALL: [Field; 7] = [
+        Field::Name,
+        Field::Store,
+        Field::Price,
+        Field::Quantity,
+        Field::Distance,
+        Field::PickupStart,
+        Field::PickupEnd,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Store => "store",
+            Field::Price => "price",
+            Field::Quantity => "quantity",
+            Field::Distance => "distance",
+            Field::PickupStart => "pickup.start",
+            Field::PickupEnd => "pickup.end",
+        }
+    }
+
+    fn is_text(self) -> bool {
+        matches!(self, Field::Name | Field::Store)
+    }
+
+    fn is_time(self) -> bool {
+        matches!(self, Field::PickupStart | Field::PickupEnd)
+    }
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Match => "~",
+            Op::NotMatch => "!~",
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+
+    fn compare<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+}
+
+impl Filter {
+    /// Whether the listing passes the filter, `distance` is the one to the watched area.
+    /// Pickup times compare in the store's time zone and fail without a pickup interval.
+    pub fn matches(&self, listing: &TGTGListing, distance: f64) -> bool {
+        match self {
+            Filter::And(left, right) => {
+                left.matches(listing, distance) && right.matches(listing, distance)
+            }
+            Filter::Or(left, right) => {
+                left.matches(listing, distance) || right.matches(listing, distance)
+            }
+            Filter::Not(filter) => !filter.matches(listing, distance),
+            Filter::Compare(field, op, value) => {
+                let text = match field {
+                    Field::Name => Some(&listing.display_name),
+                    Field::Store => Some(&listing.store.store_name),
+                    _ => None,
+                };
+                let number = match field {
+                    Field::Price => Some(listing.item.price_including_taxes.amount()),
+                    Field::Quantity => Some(listing.items_available as f64),
+                    Field::Distance => Some(distance),
+                    _ => None,
+                };
+                let timezone = listing.store.store_time_zone;
+                let time = listing.pickup_interval.as_ref().and_then(|i| match field {
+                    Field::PickupStart => Some(i.start.with_timezone(&timezone).time()),
+                    Field::PickupEnd => Some(i.end.with_timezone(&timezone).time()),
+                    _ => None,
+                });
+                match (value, text, number, time) {
+                    (Value::Pattern(regex), Some(text), _, _) => {
+                        regex.is_match(text) == (*op == Op::Match)
+                    }
+                    (Value::Text(expected), Some(text), _, _) => {
+                        (text.to_lowercase() == expected.to_lowercase()) == (*op == Op::Eq)
+                    }
+                    (Value::Number(expected), _, Some(number), _) => op.compare(number, *expected),
+                    (Value::Time(expected), _, _, Some(time)) => op.compare(time, *expected),
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Filter::Or(..) => 1,
+            Filter::And(..) => 2,
+            Filter::Not(..) => 3,
+            Filter::Compare(..) => 4,
+        }
+    }
+
+    /// Writes the filter, in parentheses if it binds looser than its surroundings
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+/// Formats the filter the way `from_str` reads it, with parentheses only where needed.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::And(left, right) | Filter::Or(left, right) => {
+                let keyword = if matches!(self, Filter::And(..)) {
+                    "and"
+                } else {
+                    "or"
+                };
+                left.fmt_within(f, self.precedence())?;
+                write!(f, " {} ", keyword)?;
+                right.fmt_within(f, self.precedence() + 1)
+            }
+            Filter::Not(filter) => {
+                write!(f, "not ")?;
+                filter.fmt_within(f, self.precedence())
+            }
+            Filter::Compare(field, op, value) => {
+                write!(f, "{} {} {}", field.name(), op.symbol(), value)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quoted = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        match self {
+            Value::Text(text) => write!(f, "{}", quoted(text)),
+            Value::Pattern(regex) => write!(f, "{}", quoted(regex.as_str())),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Time(time) => write!(f, "{}", time.format(TIME_FORMAT)),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some((token, column)) => bail!("Unexpected {} at column {}", token, column),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(f64),
+    Time(NaiveTime),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Text(text) => write!(f, "text \"{}\"", text),
+            Token::Number(number) => write!(f, "number {}", number),
+            Token::Time(time) => write!(f, "time {}", time.format(TIME_FORMAT)),
+            Token::Op(op) => write!(f, "`{}`", op.symbol()),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+/// Splits the filter into tokens along with their column, counted from 1.
+fn tokenize(s: &str) -> anyhow::Result<Vec<(Token, usize)>> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let column = i + 1;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Op(Op::Match),
+            '!' if next == Some('~') => Token::Op(Op::NotMatch),
+            '!' if next == Some('=') => Token::Op(Op::Ne),
+            '=' => Token::Op(Op::Eq),
+            '<' if next == Some('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next == Some('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                // Backslashes only escape quotes and themselves, `\d` stays as is for regexes
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match (chars.get(i), chars.get(i + 1)) {
+                        (None, _) => bail!("Text starting at column {} is not closed", column),
+                        (Some('"'), _) => break,
+                        (Some('\\'), Some(escaped @ ('"' | '\\'))) => {
+                            text.push(*escaped);
+                            i += 2;
+                        }
+                        (Some(c), _) => {
+                            text.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push((Token::Text(text), column));
+                continue;
+            }
+            c if c.is_ascii_digit() => {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_ascii_digit() || chars[j] == '.' || chars[j] == ':'))
+                    .unwrap_or(chars.len());
+                let literal = chars[i..end].iter().collect::<String>();
+                i = end;
+                let token = if literal.contains(':') {
+                    NaiveTime::parse_from_str(&literal, TIME_FORMAT)
+                        .map(Token::Time)
+                        .map_err(|_| {
+                            anyhow!("Invalid time {} at column {}, use HH:MM", literal, column)
+                        })?
+                } else {
+                    literal
+                        .parse()
+                        .map(Token::Number)
+                        .map_err(|_| anyhow!("Invalid number {} at column {}", literal, column))?
+                };
+                tokens.push((token, column));
+                continue;
+            }
+            c if c.is_alphabetic() => {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '.' || chars[j] == '_'))
+                    .unwrap_or(chars.len());
+                let word = chars[i..end].iter().collect::<String>();
+                i = end;
+                tokens.push((Token::Word(word.to_lowercase()), column));
+                continue;
+            }
+            c => bail!("Unexpected `{}` at column {}", c, column),
+        };
+        i += match token {
+            Token::Op(Op::NotMatch | Op::Ne | Op::Le | Op::Ge) => 2,
+            _ => 1,
+        };
+        tokens.push((token, column));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, `or` binds loosest and `not` tightest.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some((Token::Word(word), _)) if word == keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.and()?;
+        while self.keyword("or") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.not()?;
+        while self.keyword("and") {
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Filter> {
+        if self.keyword("not") {
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Filter> {
+        match self.advance() {
+            Some((Token::Open, column)) => {
+                let filter = self.or()?;
+                match self.advance() {
+                    Some((Token::Close, _)) => Ok(filter),
+                    _ => bail!("Parenthesis opened at column {} is not closed", column),
+                }
+            }
+            Some((Token::Word(word), column)) => {
+                let Some(field) = Field::ALL.into_iter().find(|f| f.name() == word) else {
+                    let names = Field::ALL.map(Field::name);
+                    bail!(
+                        "Unknown field `{}` at column {}, use one of {}",
+                        word,
+                        column,
+                        names.join(", ")
+                    );
+                };
+                self.comparison(field)
+            }
+            Some((token, column)) => bail!(
+                "Expected a comparison like `price < 5` at column {}, found {}",
+                column,
+                token
+            ),
+            None => bail!("Filter ended early, expected a comparison like `price < 5`"),
+        }
+    }
+
+    fn comparison(&mut self, field: Field) -> anyhow::Result<Filter> {
+        let (op, column) = match self.advance() {
+            Some((Token::Op(op), column)) => (op, column),
+            Some((token, column)) => bail!(
+                "Expected an operator after {} at column {}, found {}",
+                field.name(),
+                column,
+                token
+            ),
+            None => bail!(
+                "Filter ended early, expected an operator after {}",
+                field.name()
+            ),
+        };
+        let allowed = if field.is_text() {
+            [Op::Match, Op::NotMatch, Op::Eq, Op::Ne].contains(&op)
+        } else {
+            ![Op::Match, Op::NotMatch].contains(&op)
+        };
+        if !allowed {
+            bail!(
+                "`{}` doesn't work on {} at column {}, use {}",
+                op.symbol(),
+                field.name(),
+                column,
+                if field.is_text() {
+                    "~, !~, = or !="
+                } else {
+                    "=, !=, <, <=, > or >="
+                }
+            );
+        }
+        let value = match (self.advance(), field) {
+            (Some((Token::Text(text), column)), f) if f.is_text() => match op {
+                Op::Match | Op::NotMatch => RegexBuilder::new(&text)
+                    .case_insensitive(true)
+                    .build()
+                    .map(Value::Pattern)
+                    .map_err(|why| anyhow!("Invalid regex at column {}: {}", column, why))?,
+                _ => Value::Text(text),
+            },
+            (Some((Token::Time(time), _)), f) if f.is_time() => Value::Time(time),
+            (Some((Token::Number(number), _)), f) if !f.is_text() && !f.is_time() => {
+                Value::Number(number)
+            }
+            (found, f) => {
+                let expected = if f.is_text() {
+                    "a quoted text"
+                } else if f.is_time() {
+                    "a time like 17:30"
+                } else {
+                    "a number"
+                };
+                match found {
+                    Some((token, column)) => bail!(
+                        "Expected {} after {} {} at column {}, found {}",
+                        expected,
+                        f.name(),
+                        op.symbol(),
+                        column,
+                        token
+                    ),
+                    None => bail!(
+                        "Filter ended early, expected {} after {} {}",
+                        expected,
+                        f.name(),
+                        op.symbol()
+                    ),
+                }
+            }
+        };
+        Ok(Filter::Compare(field, op, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::TGTGConfig;
+    use crate::source::{FakeSource, ListingSource};
+
+    fn error(s: &str) -> String {
+        s.parse::<Filter>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_and_display() -> anyhow::Result<()> {
+        let filter =
+            r#"store ~ "bakery" AND price < 5 and (distance<=1.5 or not pickup.start > 17:00)"#
+                .parse::<Filter>()?;
+        assert_eq!(
+            filter.to_string(),
+            r#"store ~ "bakery" and price < 5 and (distance <= 1.5 or not pickup.start > 17:00)"#
+        );
+        let escaped = r#"name !~ "\d+ \"xl\"""#.parse::<Filter>()?;
+        assert_eq!(escaped.to_string(), r#"name !~ "\\d+ \"xl\"""#);
+        assert_eq!(
+            escaped.to_string().parse::<Filter>()?.to_string(),
+            escaped.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            error("colour = \"red\""),
+            "Unknown field `colour` at column 1, use one of name, store, price, quantity, \
+             distance, pickup.start, pickup.end"
+        );
+        assert_eq!(
+            error("name < 5"),
+            "`<` doesn't work on name at column 6, use ~, !~, = or !="
+        );
+        assert_eq!(
+            error("price < \"cheap\""),
+            "Expected a number after price < at column 9, found text \"cheap\""
+        );
+        assert_eq!(
+            error("pickup.end < 25:00"),
+            "Invalid time 25:00 at column 14, use HH:MM"
+        );
+        assert_eq!(
+            error("(price < 5"),
+            "Parenthesis opened at column 1 is not closed"
+        );
+        assert_eq!(
+            error("price < 5 quantity > 1"),
+            "Unexpected `quantity` at column 11"
+        );
+        assert!(error("name ~ \"(\"").starts_with("Invalid regex at column 8"));
+        assert_eq!(
+            error("store ~ \"bakery"),
+            "Text starting at column 9 is not closed"
+        );
+        assert_eq!(
+            error("price <"),
+            "Filter ended early, expected a number after price <"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_matches() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listings"])?;
+        let items = source.get_items(&TGTGConfig::new(52.3676, 4.9041)).await?;
+        let names = |filter: &str| -> anyhow::Result<Vec<String>> {
+            let filter = filter.parse::<Filter>()?;
+            Ok(items
+                .iter()
+                .filter(|i| filter.matches(i, 1.0))
+                .map(|i| i.item.item_id.clone())
+                .collect())
+        };
+        assert_eq!(names(r#"store ~ "BAKERY""#)?, vec!["1001"]);
+        // As regexes converted from before filters existed are stored
+        assert!(names(r#"store ~ "(?-i)BAKERY""#)?.is_empty());
+        assert_eq!(names(r#"store = "sushi centraal""#)?, vec!["1002"]);
+        assert_eq!(names("price < 5")?, vec!["1001"]);
+        assert_eq!(names("price >= 3.99 and not price > 5")?, vec!["1001"]);
+        assert_eq!(names("distance < 1")?, Vec::<String>::new());
+        assert_eq!(names(r#"name ~ "bread" or price > 5"#)?.len(), 2);
+        // Pickup at 18:00 in Amsterdam, the sushi has no pickup interval
+        assert_eq!(names("pickup.start >= 18:00")?, vec!["1001"]);
+        assert_eq!(names("not pickup.start < 17:00")?.len(), 2);
+        Ok(())
+    }
+}
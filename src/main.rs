@@ -1,21 +1,36 @@
 // mod commands;
+mod cli;
 mod data;
 mod db;
 mod discord;
+mod filter;
 mod monitor;
+mod pickup;
+mod schedule;
+mod scheduler;
 mod signal;
+mod sink;
+mod source;
+mod stats;
 mod tgtg;
 
 use std::{collections::HashSet, env, sync::Arc, time::Duration};
 
-use data::{DiscordData, TGTGBindings};
+use data::DiscordData;
 use discord::framework::DiscordClient;
+use discord::sink::DiscordSink;
+use scheduler::Scheduler;
+use source::ListingSource;
+use tgtg::{Credentials, RequestBudget};
 
 use poise::serenity_prelude as serenity;
 
-use serenity::all::GatewayIntents;
-use tokio::sync::RwLock;
-use tracing::error;
+use serenity::all::{ChannelId, GatewayIntents};
+use tokio::sync::{RwLock, oneshot};
+use tracing::{error, info, warn};
+
+/// Default cap on TGTG requests per minute, summed over all channels
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 20;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -25,46 +40,102 @@ async fn main() -> anyhow::Result<()> {
     // Initialize the logger to use environment variables.
     tracing_subscriber::fmt::init();
 
-    // Check python
-    tgtg::check_python()?;
-
-    let discord_token = env::var("DISCORD_TOKEN")?;
-    let tgtg_access_token = env::var("TGTG_ACCESS_TOKEN")?;
-    let tgtg_refresh_token = env::var("TGTG_REFRESH_TOKEN")?;
-    let tgtg_user_id = env::var("TGTG_USER_ID")?;
-    let tgtg_cookie = env::var("TGTG_COOKIE")?;
+    let command = cli::Command::parse(env::args().skip(1))?;
     let db_url = env::var("DATABASE_URL")?;
 
     // Bot DB
     let bot_db = Arc::new(db::BotDB::new(&db_url).await?);
+    if let Some(command) = command {
+        return command.run(&bot_db).await;
+    }
+
+    let discord_token = env::var("DISCORD_TOKEN")?;
+    let keep_listings = matches!(
+        env::var("KEEP_LISTINGS_ON_SHUTDOWN").as_deref(),
+        Ok("1" | "true")
+    );
     let (location_map, active_set) = bot_db.get_locations().await?;
 
+    // Stored credentials are the latest refreshed ones, prefer them over the environment
+    let tgtg_credentials = match bot_db.get_credentials().await? {
+        Some(credentials) => {
+            info!("Using TGTG credentials from the database");
+            credentials
+        }
+        None => match (
+            env::var("TGTG_ACCESS_TOKEN"),
+            env::var("TGTG_REFRESH_TOKEN"),
+            env::var("TGTG_USER_ID"),
+            env::var("TGTG_COOKIE"),
+        ) {
+            (Ok(access_token), Ok(refresh_token), Ok(user_id), Ok(cookie)) => Credentials {
+                access_token,
+                refresh_token,
+                user_id,
+                cookie,
+            },
+            _ => {
+                warn!("No TGTG credentials found, log in with the login command first");
+                Credentials::default()
+            }
+        },
+    };
+
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT;
 
     let active_channels = Arc::new(RwLock::new(HashSet::new()));
-    let tgtg_bindings = Arc::new(TGTGBindings {
-        client: crate::tgtg::init_client(
-            &tgtg_access_token,
-            &tgtg_refresh_token,
-            &tgtg_user_id,
-            &tgtg_cookie,
-        )?,
-        fetch_func: crate::tgtg::init_fetch_func()?,
+    let requests_per_minute = match env::var("TGTG_REQUESTS_PER_MINUTE") {
+        Ok(value) => value.parse()?,
+        Err(_) => DEFAULT_REQUESTS_PER_MINUTE,
+    };
+    let budget = Arc::new(RequestBudget::per_minute(requests_per_minute));
+    let tgtg_client = crate::tgtg::init_client(tgtg_credentials, budget)?;
+    let listing_source: Arc<dyn ListingSource> = tgtg_client.clone();
+    let (data_sender, data_receiver) = oneshot::channel();
+    let mut client = DiscordClient::new(&discord_token, intents, data_receiver).await?;
+    let sink = Arc::new(DiscordSink::new(client.serenity_client.http.clone()));
+    // One scheduler polls TGTG for every channel, sharing fetches between overlapping locations
+    let scheduler = Arc::new(match env::var("ADMIN_CHANNEL_ID") {
+        Ok(admin_channel) => Scheduler::new_with_admin(
+            listing_source,
+            sink.clone(),
+            ChannelId::new(admin_channel.parse()?),
+        ),
+        Err(_) => Scheduler::new(listing_source),
+    });
+    scheduler.spawn();
+
+    // Persist every token refresh so that a restart doesn't rely on stale env tokens
+    let mut credential_updates = tgtg_client.credential_updates();
+    let credentials_db = bot_db.clone();
+    let credentials_scheduler = scheduler.clone();
+    tokio::spawn(async move {
+        while credential_updates.changed().await.is_ok() {
+            let credentials = credential_updates.borrow_and_update().clone();
+            match credentials_db.set_credentials(&credentials).await {
+                Ok(()) => info!("Refreshed TGTG credentials saved to the database"),
+                Err(why) => error!("Could not save refreshed TGTG credentials: {:?}", why),
+            }
+            credentials_scheduler.credentials_changed().await;
+        }
     });
     let tgtg_configs = Arc::new(RwLock::new(location_map));
 
+    let monitor_db = bot_db.clone();
     let dc_data = DiscordData {
         bot_db,
         active_channels: active_channels.clone(),
-        tgtg_bindings: tgtg_bindings.clone(),
+        scheduler: scheduler.clone(),
+        tgtg_client: tgtg_client.clone(),
         tgtg_configs: tgtg_configs.clone(),
     };
 
-    let mut client = DiscordClient::new(&discord_token, intents, dc_data).await?;
+    if data_sender.send(dc_data).is_err() {
+        anyhow::bail!("Discord client dropped before receiving its data");
+    }
 
-    let http = client.serenity_client.http.clone();
     let active_channels_clone = active_channels.clone();
     tokio::spawn(async move {
         // wait 10 secs first to let the bot connect to discord
@@ -72,9 +143,10 @@ async fn main() -> anyhow::Result<()> {
         for (channel_id, config) in tgtg_configs.read().await.iter() {
             if active_set.contains(channel_id) {
                 let cm = crate::monitor::ChannelMonitor::init(
-                    http.clone(),
+                    sink.clone(),
+                    monitor_db.clone(),
                     channel_id.to_owned(),
-                    tgtg_bindings.clone(),
+                    scheduler.clone(),
                     config.to_owned(),
                 );
                 let mut active_channels = active_channels.write().await;
@@ -88,8 +160,17 @@ async fn main() -> anyhow::Result<()> {
             error!("Client error: {:?}", why);
         },
         _ = signal::wait_for_signal() => {
-            // clean up active channels first before shutting down
-            active_channels_clone.write().await.clear();
+            let monitors = active_channels_clone.write().await.drain().collect::<Vec<_>>();
+            if keep_listings {
+                // Listings stay persisted, the next run picks them up and keeps them up to date
+                info!("Keeping {} channels' listings on shutdown", monitors.len());
+                drop(monitors);
+            } else {
+                // clean up active channels first before shutting down
+                for monitor in monitors {
+                    monitor.stop().await;
+                }
+            }
         }
     }
     Ok(())
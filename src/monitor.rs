@@ -1,227 +1,818 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::futures::stream;
 use poise::serenity_prelude::futures::StreamExt as _;
-use serenity::builder::CreateEmbed;
-use serenity::builder::CreateMessage;
-use serenity::builder::EditMessage;
+use serenity::model::id::{ChannelId, MessageId, RoleId};
 use serenity::prelude::RwLock;
-use serenity::{http::Http, model::id::ChannelId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tracing::info;
 use tracing::warn;
 
+use crate::data::DigestPage;
+use crate::data::DigestSort;
 use crate::data::ItemMessage;
+use crate::data::ListingEvent;
+use crate::db::BotDB;
 use crate::data::TGTGConfig;
-use crate::data::OSM_ZOOM_LEVEL;
-use crate::data::RADIUS_UNIT;
-use crate::TGTGBindings;
+use crate::data::TGTGListing;
+use crate::scheduler::Scheduler;
+use crate::sink::{ListingSink, SinkError};
+use crate::stats::upcoming_releases;
 
-const MONITOR_INTERVAL: u64 = 60;
+/// How often the monitor looks for releases to announce
+const PREDICTION_CHECK: Duration = Duration::from_secs(60);
+/// How long before the usual release time the heads-up is posted
+const PREDICTION_LEAD: chrono::Duration = chrono::Duration::minutes(10);
+/// Days of quantity changes the release patterns are learned from
+const PREDICTION_HISTORY_DAYS: i64 = 28;
+/// How long polling stays boosted after the expected release time
+const BOOST_AFTER: Duration = Duration::from_secs(15 * 60);
+/// How long after notifying about an item its restocks are posted without a mention or DM
+const NOTIFY_COOLDOWN: chrono::Duration = chrono::Duration::hours(1);
+/// How soon after selling out a listing coming back counts as a restock instead of new
+const RESTOCK_WINDOW: chrono::Duration = chrono::Duration::hours(6);
+/// Most listings on a page of a digest, discord cuts off longer embeds
+const DIGEST_PAGE_SIZE: usize = 20;
+/// Most DMs about a listing on their way at the same time
+const DM_CONCURRENCY: usize = 5;
 
 pub struct ChannelMonitor {
     pub channel_id: ChannelId,
-    http: Arc<Http>,
+    sink: Arc<dyn ListingSink>,
+    bot_db: Arc<BotDB>,
+    scheduler: Arc<Scheduler>,
     handle: JoinHandle<()>,
     messages: Arc<RwLock<HashMap<String, ItemMessage>>>,
 }
 
 impl ChannelMonitor {
     pub fn init(
-        http: Arc<Http>,
+        sink: Arc<dyn ListingSink>,
+        bot_db: Arc<BotDB>,
         channel_id: ChannelId,
-        tgtg_bindings: Arc<TGTGBindings>,
+        scheduler: Arc<Scheduler>,
         tgtg_config: TGTGConfig,
     ) -> Self {
         info!("Channel {}: Monitor starting (DB) ", channel_id);
         let messages = Arc::new(RwLock::new(HashMap::new()));
         let loop_messages = messages.clone();
-        let loop_http = http.clone();
+        let loop_sink = sink.clone();
+        let loop_db = bot_db.clone();
+        let loop_scheduler = scheduler.clone();
+        let loop_config = tgtg_config.clone();
+        let mut receiver = scheduler.subscribe(channel_id, tgtg_config);
         let handle = tokio::spawn(async move {
-            loop {
-                let res = ChannelMonitor::update_location(
-                    tgtg_bindings.clone(),
-                    loop_http.clone(),
-                    channel_id,
-                    tgtg_config.clone(),
-                    loop_messages.clone(),
-                )
-                .await;
-                if let Err(why) = res {
+            // Pick up the messages of a previous run, the first update edits or deletes them
+            match loop_db.get_item_messages(channel_id).await {
+                Ok(item_messages) => {
+                    info!(
+                        "Channel {}: Monitor loaded {} messages",
+                        channel_id,
+                        item_messages.len()
+                    );
+                    *loop_messages.write().await = item_messages;
+                }
+                Err(why) => warn!("Channel {}: Failed to load messages with {}", channel_id, why),
+            }
+            let mut predictions = tokio::time::interval(PREDICTION_CHECK);
+            let mut announced = HashSet::new();
+            // Listed quantities of the last digest update, those of a previous run at first
+            let mut quantities = match loop_db.get_open_quantities(channel_id).await {
+                Ok(quantities) => quantities,
+                Err(why) => {
                     warn!(
-                        "Channel {}: Failed to update location with {}",
+                        "Channel {}: Failed to load open listings with {}",
                         channel_id, why
                     );
+                    HashMap::new()
+                }
+            };
+            loop {
+                tokio::select! {
+                    items = receiver.recv() => {
+                        let Some(items) = items else {
+                            break;
+                        };
+                        let config = loop_scheduler
+                            .config(channel_id)
+                            .unwrap_or_else(|| loop_config.clone());
+                        let res = if config.digest.is_some() {
+                            ChannelMonitor::update_digest(
+                                loop_sink.clone(),
+                                &loop_db,
+                                channel_id,
+                                loop_messages.clone(),
+                                &mut quantities,
+                                items,
+                                &config,
+                            )
+                            .await
+                        } else {
+                            quantities.clear();
+                            ChannelMonitor::update_location(
+                                loop_sink.clone(),
+                                &loop_db,
+                                channel_id,
+                                loop_messages.clone(),
+                                items,
+                                &config,
+                            )
+                            .await
+                        };
+                        if let Err(why) = res {
+                            warn!(
+                                "Channel {}: Failed to update location with {}",
+                                channel_id, why
+                            );
+                        }
+                    }
+                    _ = predictions.tick() => {
+                        let res = ChannelMonitor::announce_releases(
+                            loop_sink.as_ref(),
+                            &loop_db,
+                            &loop_scheduler,
+                            channel_id,
+                            &mut announced,
+                            Utc::now(),
+                        )
+                        .await;
+                        if let Err(why) = res {
+                            warn!(
+                                "Channel {}: Failed to predict releases with {}",
+                                channel_id, why
+                            );
+                        }
+                    }
                 }
-                tokio::time::sleep(Duration::from_secs(MONITOR_INTERVAL)).await;
             }
         });
         Self {
             channel_id,
-            http,
+            sink,
+            bot_db,
+            scheduler,
             handle,
             messages,
         }
     }
 
+    /// Stops monitoring and removes all listing and digest messages from the discord channel.
+    pub async fn stop(self) {
+        self.scheduler.unsubscribe(self.channel_id);
+        self.handle.abort();
+        let channel_id = self.channel_id;
+        let item_messages = self.messages.read().await;
+        let count = stream::iter(item_messages.values())
+            .filter_map(|v| async {
+                self.sink
+                    .delete(v.channel_id(channel_id), v.message_id)
+                    .await
+                    .is_ok()
+                    .then_some(())
+            })
+            .count()
+            .await;
+        tracing::debug!(
+            "Channel {}: {} messages deleted from the discord channel",
+            channel_id, count
+        );
+        if let Err(why) = self.bot_db.delete_item_messages(channel_id).await {
+            warn!("Channel {}: Failed to forget messages with {}", channel_id, why);
+        }
+        let res = ChannelMonitor::remove_digest(self.sink.as_ref(), &self.bot_db, channel_id).await;
+        if let Err(why) = res {
+            warn!(
+                "Channel {}: Failed to remove digest with {}",
+                channel_id, why
+            );
+        }
+    }
+
+    /// Posts a heads-up for every store of the channel expected to release bags soon and
+    /// boosts polling until a while after, when the channel opted in to predictions. Each
+    /// release is announced once, `announced` keeps track of them.
+    async fn announce_releases(
+        sink: &dyn ListingSink,
+        bot_db: &BotDB,
+        scheduler: &Scheduler,
+        channel_id: ChannelId,
+        announced: &mut HashSet<(String, DateTime<Utc>)>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        if !scheduler
+            .config(channel_id)
+            .is_some_and(|config| config.predictions)
+        {
+            return Ok(());
+        }
+        let since = now - chrono::Duration::days(PREDICTION_HISTORY_DAYS);
+        let changes = bot_db.get_quantity_changes(channel_id, since, None).await?;
+        for prediction in upcoming_releases(&changes, now, PREDICTION_LEAD) {
+            if !announced.insert((prediction.store_id.clone(), prediction.expected)) {
+                continue;
+            }
+            info!(
+                "Channel {}: Release of {} expected at {}",
+                channel_id, prediction.store_name, prediction.expected
+            );
+            sink.heads_up(channel_id, &prediction).await?;
+            let until = (prediction.expected - now).to_std().unwrap_or_default() + BOOST_AFTER;
+            scheduler.boost(channel_id, Instant::now() + until);
+        }
+        announced.retain(|(_, expected)| *expected > now - chrono::Duration::days(1));
+        Ok(())
+    }
+
+    /// Whether a listing appearing now is new or restocked shortly after selling out
+    async fn appearance(
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<ListingEvent> {
+        let sold_out = bot_db
+            .get_last_sold_out(channel_id, &listing.item.item_id)
+            .await?;
+        Ok(match sold_out {
+            Some(at) if now - at < RESTOCK_WINDOW => ListingEvent::Restock,
+            _ => ListingEvent::New,
+        })
+    }
+
+    /// Whether the cooldown since the last notification about the listing has passed
+    async fn notify_due(
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        Ok(bot_db
+            .get_notified(channel_id, &listing.item.item_id)
+            .await?
+            .is_none_or(|at| now - at >= NOTIFY_COOLDOWN))
+    }
+
+    /// Starts the cooldown of the listing and sends it to the users subscribed to the channel
+    /// whose filter it matches. The DMs go out in a task of their own so that many subscribers
+    /// don't hold up the poll. Users who can't receive DMs are skipped.
+    async fn notify_subscribers(
+        sink: Arc<dyn ListingSink>,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let user_ids = bot_db
+            .get_subscriptions(channel_id)
+            .await?
+            .into_iter()
+            .filter(|s| {
+                s.filter
+                    .as_ref()
+                    .is_none_or(|f| f.matches(listing, listing.distance))
+            })
+            .map(|s| s.user_id)
+            .collect::<Vec<_>>();
+        bot_db
+            .set_notified(channel_id, &listing.item.item_id, now)
+            .await?;
+        let listing = listing.clone();
+        tokio::spawn(async move {
+            stream::iter(user_ids)
+                .for_each_concurrent(DM_CONCURRENCY, |user_id| {
+                    let (sink, listing) = (&sink, &listing);
+                    async move {
+                        if let Err(why) = sink.direct_message(user_id, channel_id, listing).await {
+                            warn!(
+                                "Channel {}: Failed to message user {} with {}",
+                                channel_id, user_id, why
+                            );
+                        }
+                    }
+                })
+                .await;
+        });
+        Ok(())
+    }
+
+    /// Posts, edits and deletes the listing messages to match the fetched items, which the
+    /// scheduler already filtered by the watches and filters of the channel. Messages of
+    /// items which aren't listed anymore are deleted as well, e.g. those left by a previous
+    /// run or left out by changed filters. The events the channel is notified of reply to
+    /// the listing message, and new listings or restocks mention the role and notify the
+    /// subscribers unless the item was notified about recently. While restocks notify, sold
+    /// out messages are kept for a restock to reply to until `remove_sold_out` deletes them.
     async fn update_location(
-        tgtg_bindings: Arc<TGTGBindings>,
-        http: Arc<Http>,
+        sink: Arc<dyn ListingSink>,
+        bot_db: &BotDB,
         channel_id: ChannelId,
-        config: TGTGConfig,
         messages: Arc<RwLock<HashMap<String, ItemMessage>>>,
+        items: Vec<TGTGListing>,
+        config: &TGTGConfig,
     ) -> anyhow::Result<()> {
-        let items = crate::tgtg::get_items(&tgtg_bindings, &config)?;
         info!(
             "Channel {}: Monitor found {} items",
             channel_id,
             items.len()
         );
+        // Switched from the digest mode, the listing messages take over
+        ChannelMonitor::remove_digest(sink.as_ref(), bot_db, channel_id).await?;
         let almost_now = Utc::now();
+        let mut listed = HashSet::new();
         for i in items {
             let item_message = {
                 let item_map = messages.read().await;
                 item_map.get(&i.item.item_id).copied()
             };
-            // check regex
-            if let Some(regex) = config.regex.as_ref() {
-                if !regex.is_match(&i.display_name) {
-                    info!(
-                        "Channel {}: Item {} with quantity {} - not matching regex",
-                        channel_id, i.display_name, i.items_available
-                    );
-                    continue;
-                }
-            }
             info!(
-                "Channel {}: Item {} with quantity {} - matching regex",
+                "Channel {}: Item {} with quantity {}",
                 channel_id, i.display_name, i.items_available
             );
+            listed.insert(i.item.item_id.clone());
             //  Check if the item is available and if we are in the purchase time period
-            if i.purchase_end
-                .map(|end_time| end_time > almost_now)
-                .is_some()
-                && i.items_available > 0
-            {
-                // Construct a new message embed with quantity and date to post or update
-                let mut embed = CreateEmbed::new()
-                    .title(i.store.store_name)
-                    .description(i.display_name)
-                    .field(
-                        "Price",
-                        format!(
-                            "{:.2} {}",
-                            i.item.price_including_taxes.minor_units as f64
-                                / 10u32.pow(i.item.price_including_taxes.decimals) as f64,
-                            i.item.price_including_taxes.code
-                        ),
-                        true,
-                    )
-                    .field("Quantity", format!("{}", i.items_available), true)
-                    .field(
-                        "Distance",
-                        format!("{:.2} {}", i.distance, RADIUS_UNIT),
-                        true,
-                    )
-                    .image(i.store.logo_picture.current_url)
-                    .url(format!(
-                        "https://www.openstreetmap.org/#map={}/{:.4}/{:.4}",
-                        OSM_ZOOM_LEVEL,
-                        i.pickup_location.location.latitude,
-                        i.pickup_location.location.longitude
-                    ));
-                if let Some(interval) = i.pickup_interval {
-                    let timezone = i.store.store_time_zone;
-                    embed = embed.field(
-                        "Pickup interval",
-                        format!(
-                            "{} - {}",
-                            interval
-                                .start
-                                .with_timezone(&timezone)
-                                .format("%a %H:%M %Z"),
-                            interval.end.with_timezone(&timezone).format("%a %H:%M %Z")
-                        ),
-                        true,
-                    );
-                }
+            if is_available(&i, almost_now) {
+                // Update the message with the new quantity, one deleted by hand is posted again
+                let item_message = match item_message {
+                    Some(item_message) if item_message.quantity != i.items_available => {
+                        let message_channel = item_message.channel_id(channel_id);
+                        match sink
+                            .edit(message_channel, item_message.message_id, &i)
+                            .await
+                        {
+                            Ok(()) => Some(item_message),
+                            Err(why) if SinkError::is(&why, SinkError::UnknownMessage) => {
+                                warn!(
+                                    "Channel {}: Message of item {} is gone, posting it again",
+                                    channel_id, i.display_name
+                                );
+                                bot_db
+                                    .delete_item_message(channel_id, &i.item.item_id)
+                                    .await?;
+                                messages.write().await.remove(&i.item.item_id);
+                                None
+                            }
+                            Err(why) => return Err(why),
+                        }
+                    }
+                    item_message => item_message,
+                };
                 if let Some(item_message) = item_message {
-                    // Update the message with the new quantity
                     if item_message.quantity != i.items_available {
-                        let builder = EditMessage::new().embed(embed);
-                        channel_id
-                            .edit_message(&http, item_message.message_id, builder)
+                        let event = if i.items_available > item_message.quantity {
+                            ListingEvent::Restock
+                        } else {
+                            ListingEvent::QuantityDrop
+                        };
+                        let notify = config.notifies(event)
+                            && event == ListingEvent::Restock
+                            && ChannelMonitor::notify_due(bot_db, channel_id, &i, almost_now)
+                                .await?;
+                        // Saved first, a failed reply mustn't edit and reply again next time
+                        let item_message = ItemMessage {
+                            quantity: i.items_available,
+                            ..item_message
+                        };
+                        bot_db
+                            .set_item_message(channel_id, &i.item.item_id, &item_message)
                             .await?;
-                        let mut items_map = messages.write().await;
-                        items_map.insert(
-                            i.item.item_id,
-                            ItemMessage {
-                                message_id: item_message.message_id,
-                                quantity: i.items_available,
-                            },
-                        );
+                        bot_db.record_listing(channel_id, &i, almost_now).await?;
+                        bot_db.record_quantity(channel_id, &i, almost_now).await?;
+                        messages
+                            .write()
+                            .await
+                            .insert(i.item.item_id.clone(), item_message);
+                        if config.notifies(event) {
+                            let mention = config.mention_role.filter(|_| notify);
+                            sink.reply(
+                                item_message.channel_id(channel_id),
+                                item_message.message_id,
+                                &i,
+                                event,
+                                mention,
+                            )
+                            .await?;
+                        }
+                        if notify {
+                            ChannelMonitor::notify_subscribers(
+                                sink.clone(),
+                                bot_db,
+                                channel_id,
+                                &i,
+                                almost_now,
+                            )
+                            .await?;
+                        }
                     }
                 } else {
                     // We have quantity available, post a new message
-                    let builder = CreateMessage::new().add_embed(embed);
-                    let msg = channel_id.send_message(&http, builder).await?;
-                    let mut items_map = messages.write().await;
-                    items_map.insert(
-                        i.item.item_id,
-                        ItemMessage {
-                            message_id: msg.id,
-                            quantity: i.items_available,
-                        },
-                    );
+                    let event =
+                        ChannelMonitor::appearance(bot_db, channel_id, &i, almost_now).await?;
+                    let notify = config.notifies(event)
+                        && ChannelMonitor::notify_due(bot_db, channel_id, &i, almost_now).await?;
+                    let mention = config.mention_role.filter(|_| notify);
+                    let (message_id, thread_id) = ChannelMonitor::post_listing(
+                        sink.as_ref(),
+                        bot_db,
+                        channel_id,
+                        &i,
+                        mention,
+                        config,
+                    )
+                    .await?;
+                    // Persisted first, a failure after posting mustn't post the listing again
+                    let item_message = ItemMessage {
+                        message_id,
+                        quantity: i.items_available,
+                        thread_id,
+                    };
+                    bot_db
+                        .set_item_message(channel_id, &i.item.item_id, &item_message)
+                        .await?;
+                    bot_db.record_listing(channel_id, &i, almost_now).await?;
+                    bot_db.record_quantity(channel_id, &i, almost_now).await?;
+                    messages
+                        .write()
+                        .await
+                        .insert(i.item.item_id.clone(), item_message);
+                    if notify {
+                        ChannelMonitor::notify_subscribers(
+                            sink.clone(),
+                            bot_db,
+                            channel_id,
+                            &i,
+                            almost_now,
+                        )
+                        .await?;
+                    }
                 }
-            } else {
-                // No quantity or purchase period has passed. Check we posted this item before, if yes delete
-                if let Some(item_message) = item_message {
-                    channel_id
-                        .delete_message(&http, item_message.message_id)
+            } else if let Some(item_message) = item_message.filter(|m| m.quantity > 0) {
+                // Sold out or the purchase period has passed, only a sell-out can be restocked
+                let sold_out = i.items_available == 0;
+                let message_channel = item_message.channel_id(channel_id);
+                if sold_out && config.notifies(ListingEvent::Restock) {
+                    // Kept within the restock window, a restock replies to the message
+                    let item_message = ItemMessage {
+                        quantity: 0,
+                        ..item_message
+                    };
+                    match sink
+                        .edit(message_channel, item_message.message_id, &i)
+                        .await
+                    {
+                        Ok(()) => {
+                            if config.notifies(ListingEvent::SoldOut) {
+                                sink.reply(
+                                    message_channel,
+                                    item_message.message_id,
+                                    &i,
+                                    ListingEvent::SoldOut,
+                                    None,
+                                )
+                                .await?;
+                            }
+                            bot_db
+                                .set_item_message(channel_id, &i.item.item_id, &item_message)
+                                .await?;
+                            messages
+                                .write()
+                                .await
+                                .insert(i.item.item_id.clone(), item_message);
+                        }
+                        Err(why) if SinkError::is(&why, SinkError::UnknownMessage) => {
+                            bot_db
+                                .delete_item_message(channel_id, &i.item.item_id)
+                                .await?;
+                            messages.write().await.remove(&i.item.item_id);
+                        }
+                        Err(why) => return Err(why),
+                    }
+                } else {
+                    sink.delete(message_channel, item_message.message_id)
                         .await?;
-                    let mut items_map = messages.write().await;
-                    items_map.remove(&i.item.item_id);
+                    if sold_out && config.notifies(ListingEvent::SoldOut) {
+                        sink.notice(message_channel, &format!("{} sold out!", i.display_name))
+                            .await?;
+                    }
+                    bot_db
+                        .delete_item_message(channel_id, &i.item.item_id)
+                        .await?;
+                    messages.write().await.remove(&i.item.item_id);
                 }
+                bot_db
+                    .close_listing(channel_id, &i.item.item_id, almost_now, sold_out)
+                    .await?;
+                bot_db
+                    .record_closed(channel_id, &i.item.item_id, almost_now, sold_out)
+                    .await?;
             }
         }
+        let unlisted = messages
+            .read()
+            .await
+            .iter()
+            .filter(|(item_id, m)| !listed.contains(*item_id) && m.quantity > 0)
+            .map(|(item_id, item_message)| (item_id.clone(), *item_message))
+            .collect::<Vec<_>>();
+        for (item_id, item_message) in unlisted {
+            info!(
+                "Channel {}: Item {} isn't listed anymore, deleting its message",
+                channel_id, item_id
+            );
+            // The message might be gone already, forget it either way
+            if let Err(why) = sink
+                .delete(item_message.channel_id(channel_id), item_message.message_id)
+                .await
+            {
+                warn!("Channel {}: Failed to delete message with {}", channel_id, why);
+            }
+            bot_db.delete_item_message(channel_id, &item_id).await?;
+            // Left out by the filters, which isn't selling out
+            bot_db
+                .close_listing(channel_id, &item_id, almost_now, false)
+                .await?;
+            bot_db
+                .record_closed(channel_id, &item_id, almost_now, false)
+                .await?;
+            messages.write().await.remove(&item_id);
+        }
+        ChannelMonitor::remove_sold_out(
+            sink.as_ref(),
+            bot_db,
+            channel_id,
+            messages,
+            config,
+            almost_now,
+        )
+        .await
+    }
+
+    /// Deletes the messages kept after selling out once a restock can't reply to them anymore,
+    /// after `RESTOCK_WINDOW` or when the channel isn't notified of restocks.
+    async fn remove_sold_out(
+        sink: &dyn ListingSink,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        messages: Arc<RwLock<HashMap<String, ItemMessage>>>,
+        config: &TGTGConfig,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let sold_out = messages
+            .read()
+            .await
+            .iter()
+            .filter(|(_, item_message)| item_message.quantity == 0)
+            .map(|(item_id, item_message)| (item_id.clone(), *item_message))
+            .collect::<Vec<_>>();
+        for (item_id, item_message) in sold_out {
+            let restockable = config.notifies(ListingEvent::Restock)
+                && bot_db
+                    .get_last_sold_out(channel_id, &item_id)
+                    .await?
+                    .is_some_and(|at| now - at < RESTOCK_WINDOW);
+            if restockable {
+                continue;
+            }
+            // The message might be gone already, forget it either way
+            if let Err(why) = sink
+                .delete(item_message.channel_id(channel_id), item_message.message_id)
+                .await
+            {
+                warn!("Channel {}: Failed to delete message with {}", channel_id, why);
+            }
+            bot_db.delete_item_message(channel_id, &item_id).await?;
+            messages.write().await.remove(&item_id);
+        }
+        Ok(())
+    }
+
+    /// Posts a new listing message, in the thread of its store when the channel has threads
+    /// enabled, and returns its id along with the thread. A thread deleted, archived or
+    /// locked by hand is started again.
+    async fn post_listing(
+        sink: &dyn ListingSink,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        mention: Option<RoleId>,
+        config: &TGTGConfig,
+    ) -> anyhow::Result<(MessageId, Option<ChannelId>)> {
+        if !config.threads {
+            let message_id = sink.post(channel_id, listing, mention).await?;
+            return Ok((message_id, None));
+        }
+        let store_id = &listing.store.store_id;
+        if let Some(thread_id) = bot_db.get_store_thread(channel_id, store_id).await? {
+            match sink.post(thread_id, listing, mention).await {
+                Ok(message_id) => return Ok((message_id, Some(thread_id))),
+                // Deleted, archived or locked by hand, other failures are retried next time
+                Err(why) if SinkError::is(&why, SinkError::UnavailableChannel) => warn!(
+                    "Channel {}: Failed to post in thread {} with {}, starting it again",
+                    channel_id, thread_id, why
+                ),
+                Err(why) => return Err(why),
+            }
+        }
+        let thread_id = sink
+            .create_thread(channel_id, &listing.store.store_name)
+            .await?;
+        info!(
+            "Channel {}: Thread {} started for {}",
+            channel_id, thread_id, listing.store.store_name
+        );
+        bot_db
+            .set_store_thread(channel_id, store_id, thread_id)
+            .await?;
+        let message_id = sink.post(thread_id, listing, mention).await?;
+        Ok((message_id, Some(thread_id)))
+    }
+
+    /// Keeps the pinned summary of the channel in digest mode up to date with the fetched
+    /// items, with `DIGEST_PAGE_SIZE` listings per page. Listing messages left from the
+    /// listings mode are removed first. `quantities` holds the listed quantities of the last
+    /// update, to record the changes and notify the subscribers like `update_location`.
+    async fn update_digest(
+        sink: Arc<dyn ListingSink>,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        messages: Arc<RwLock<HashMap<String, ItemMessage>>>,
+        quantities: &mut HashMap<String, usize>,
+        items: Vec<TGTGListing>,
+        config: &TGTGConfig,
+    ) -> anyhow::Result<()> {
+        info!(
+            "Channel {}: Monitor found {} items for the digest",
+            channel_id,
+            items.len()
+        );
+        let almost_now = Utc::now();
+        let sold_out_ids = items
+            .iter()
+            .filter(|i| i.items_available == 0)
+            .map(|i| i.item.item_id.clone())
+            .collect::<HashSet<_>>();
+        let item_messages = std::mem::take(&mut *messages.write().await);
+        for (item_id, item_message) in item_messages {
+            if let Err(why) = sink
+                .delete(item_message.channel_id(channel_id), item_message.message_id)
+                .await
+            {
+                warn!("Channel {}: Failed to delete message with {}", channel_id, why);
+            }
+            bot_db.delete_item_message(channel_id, &item_id).await?;
+            // Messages kept after selling out are no listings anymore
+            if item_message.quantity > 0 {
+                quantities.insert(item_id, item_message.quantity);
+            }
+        }
+        let mut available = items
+            .into_iter()
+            .filter(|i| is_available(i, almost_now))
+            .collect::<Vec<_>>();
+        for i in &available {
+            match quantities.get(&i.item.item_id) {
+                Some(quantity) if *quantity == i.items_available => continue,
+                Some(_) => {}
+                None => {
+                    let event =
+                        ChannelMonitor::appearance(bot_db, channel_id, i, almost_now).await?;
+                    if config.notifies(event)
+                        && ChannelMonitor::notify_due(bot_db, channel_id, i, almost_now).await?
+                    {
+                        ChannelMonitor::notify_subscribers(
+                            sink.clone(),
+                            bot_db,
+                            channel_id,
+                            i,
+                            almost_now,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            bot_db.record_listing(channel_id, i, almost_now).await?;
+            bot_db.record_quantity(channel_id, i, almost_now).await?;
+        }
+        let listed = available
+            .iter()
+            .map(|i| (i.item.item_id.clone(), i.items_available))
+            .collect::<HashMap<_, _>>();
+        for item_id in quantities.keys().filter(|id| !listed.contains_key(*id)) {
+            // Otherwise its purchase window passed or the filters leave it out
+            let sold_out = sold_out_ids.contains(item_id);
+            bot_db
+                .close_listing(channel_id, item_id, almost_now, sold_out)
+                .await?;
+            bot_db
+                .record_closed(channel_id, item_id, almost_now, sold_out)
+                .await?;
+        }
+        *quantities = listed;
+        match config.digest {
+            Some(DigestSort::Price) => available.sort_by(|a, b| {
+                let price = |i: &TGTGListing| i.item.price_including_taxes.amount();
+                price(a)
+                    .total_cmp(&price(b))
+                    .then(a.distance.total_cmp(&b.distance))
+            }),
+            _ => available.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        }
+        ChannelMonitor::publish_digest(sink.as_ref(), bot_db, channel_id, available).await
+    }
+
+    /// Shows the listings on the summary pages of the channel, editing the posted pages.
+    /// Missing pages are posted, also when one was deleted by hand, and extra ones deleted.
+    async fn publish_digest(
+        sink: &dyn ListingSink,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+        listings: Vec<TGTGListing>,
+    ) -> anyhow::Result<()> {
+        let mut pages = listings
+            .chunks(DIGEST_PAGE_SIZE)
+            .map(<[_]>::to_vec)
+            .collect::<Vec<_>>();
+        if pages.is_empty() {
+            // An empty page tells that nothing is available
+            pages.push(Vec::new());
+        }
+        let message_ids = bot_db.get_digest_messages(channel_id).await?;
+        let count = pages.len();
+        for (n, listings) in pages.into_iter().enumerate() {
+            let page = DigestPage {
+                listings,
+                page: n,
+                pages: count,
+            };
+            if let Some(message_id) = message_ids.get(n) {
+                match sink.edit_digest(channel_id, *message_id, &page).await {
+                    Ok(()) => continue,
+                    // Deleted by hand, post it again
+                    Err(why) if SinkError::is(&why, SinkError::UnknownMessage) => warn!(
+                        "Channel {}: Digest page {} is gone, posting it again",
+                        channel_id, n
+                    ),
+                    Err(why) => return Err(why),
+                }
+            }
+            let message_id = sink.post_digest(channel_id, &page).await?;
+            bot_db.set_digest_message(channel_id, n, message_id).await?;
+        }
+        if message_ids.len() > count {
+            for message_id in &message_ids[count..] {
+                if let Err(why) = sink.delete(channel_id, *message_id).await {
+                    warn!(
+                        "Channel {}: Failed to delete digest with {}",
+                        channel_id, why
+                    );
+                }
+            }
+            bot_db.delete_digest_messages(channel_id, count).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the summary pages of the channel, if it was in digest mode.
+    async fn remove_digest(
+        sink: &dyn ListingSink,
+        bot_db: &BotDB,
+        channel_id: ChannelId,
+    ) -> anyhow::Result<()> {
+        let message_ids = bot_db.get_digest_messages(channel_id).await?;
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+        for message_id in message_ids {
+            // The message might be gone already, forget it either way
+            if let Err(why) = sink.delete(channel_id, message_id).await {
+                warn!(
+                    "Channel {}: Failed to delete digest with {}",
+                    channel_id, why
+                );
+            }
+        }
+        bot_db.delete_digest_messages(channel_id, 0).await?;
         Ok(())
     }
 }
 
+/// Whether the listing has bags left which can still be bought
+fn is_available(listing: &TGTGListing, now: DateTime<Utc>) -> bool {
+    listing.purchase_end.is_some_and(|end_time| end_time > now) && listing.items_available > 0
+}
+
 impl Drop for ChannelMonitor {
+    /// Stops monitoring but leaves the messages in the channel, they stay persisted for
+    /// the next monitor of the channel to pick up.
     fn drop(&mut self) {
         // abort watching
+        self.scheduler.unsubscribe(self.channel_id);
         self.handle.abort();
-        // remove all messages from the discord channel
-        let messages = self.messages.clone();
-        let http = self.http.clone();
-        let channel_id = self.channel_id;
-        // block_in_place ensures waiting for the block to finish even the executor is shutting down
-        tokio::task::block_in_place(move || {
-            tokio::runtime::Handle::current().block_on(async move {
-                let item_messages = messages.read().await;
-                let count = stream::iter(item_messages.values())
-                    .filter_map(|v| async {
-                        channel_id
-                            .delete_message(&http, v.message_id)
-                            .await
-                            .is_ok()
-                            .then_some(())
-                    })
-                    .count()
-                    .await;
-                tracing::debug!(
-                    "Channel {}: {} messages deleted from the discord channel",
-                    channel_id, count
-                );
-            });
-        });
         info!(
             "Channel {}: Task terminated for monitoring location",
             self.channel_id
@@ -242,3 +833,856 @@ impl PartialEq for ChannelMonitor {
 }
 
 impl Eq for ChannelMonitor {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::Subscription;
+    use crate::sink::{RecordingSink, SinkEvent};
+    use crate::source::{FakeSource, ListingSource};
+    use serenity::all::UserId;
+
+    fn channel() -> ChannelId {
+        ChannelId::new(42)
+    }
+
+    #[tokio::test]
+    async fn test_listing_lifecycle() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&[
+            "listing_appears",
+            "listing_appears",
+            "listing_quantity_changed",
+            "listing_sold_out",
+        ])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        // Without restock notifications a sold out message is deleted right away
+        config.notify = vec![ListingEvent::New];
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Post {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+                item_id: "1001".to_owned(),
+                quantity: 3,
+                mention: None,
+            }]
+        );
+
+        // Same quantity, nothing to do
+        poll().await?;
+        assert_eq!(sink.take_events(), vec![]);
+
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Edit {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+                item_id: "1001".to_owned(),
+                quantity: 1,
+            }]
+        );
+
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }]
+        );
+        assert!(messages.read().await.is_empty());
+        assert!(bot_db.get_item_messages(channel()).await?.is_empty());
+
+        // A single appearance with its highest quantity ends up in the history
+        let history = bot_db
+            .get_history(channel(), DateTime::UNIX_EPOCH, None)
+            .await?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].quantity, 3);
+        assert!(history[0].sold_out.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notifies_new_listings_once() -> anyhow::Result<()> {
+        let source =
+            FakeSource::from_fixtures(&["listing_appears", "listing_sold_out", "listing_appears"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        for (user_id, filter) in [(5, r#"store ~ "bakery""#), (6, "price > 5")] {
+            let subscription = Subscription {
+                user_id: UserId::new(user_id),
+                filter: Some(filter.parse()?),
+            };
+            bot_db.set_subscription(channel(), &subscription).await?;
+        }
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.mention_role = Some(RoleId::new(99));
+        config.notify = vec![ListingEvent::New, ListingEvent::Restock];
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll().await?;
+        sink.wait_direct_messages(1).await;
+        assert_eq!(
+            sink.take_events(),
+            vec![
+                SinkEvent::Post {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_id: "1001".to_owned(),
+                    quantity: 3,
+                    mention: config.mention_role,
+                },
+                SinkEvent::DirectMessage {
+                    user_id: UserId::new(5),
+                    item_id: "1001".to_owned(),
+                },
+            ]
+        );
+
+        // The sold out message stays around for a restock
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Edit {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+                item_id: "1001".to_owned(),
+                quantity: 0,
+            }]
+        );
+        // A restock within the cooldown replies to the sold out message quietly
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![
+                SinkEvent::Edit {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_id: "1001".to_owned(),
+                    quantity: 3,
+                },
+                SinkEvent::Reply {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_id: "1001".to_owned(),
+                    event: ListingEvent::Restock,
+                    mention: None,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_reply_isnt_repeated() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&[
+            "listing_appears",
+            "listing_quantity_changed",
+            "listing_quantity_changed",
+        ])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.notify = vec![ListingEvent::New, ListingEvent::QuantityDrop];
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll().await?;
+        sink.take_events();
+        // The edit goes through, the reply fails
+        sink.then_succeed();
+        sink.then_fail(anyhow::anyhow!("Internal server error"));
+        assert!(poll().await.is_err());
+        assert_eq!(
+            bot_db.get_item_messages(channel()).await?["1001"].quantity,
+            1
+        );
+        sink.take_events();
+        // The new quantity was saved, nothing is edited or replied again
+        poll().await?;
+        assert!(sink.take_events().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notified_events_reply() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&[
+            "listing_appears",
+            "listing_quantity_changed",
+            "listing_appears",
+            "listing_sold_out",
+        ])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.notify = vec![
+            ListingEvent::New,
+            ListingEvent::Restock,
+            ListingEvent::QuantityDrop,
+            ListingEvent::SoldOut,
+        ];
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+        let reply = |event, quantity| {
+            vec![
+                SinkEvent::Edit {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_id: "1001".to_owned(),
+                    quantity,
+                },
+                SinkEvent::Reply {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_id: "1001".to_owned(),
+                    event,
+                    mention: None,
+                },
+            ]
+        };
+
+        poll().await?;
+        assert_eq!(sink.take_events().len(), 1);
+        poll().await?;
+        assert_eq!(sink.take_events(), reply(ListingEvent::QuantityDrop, 1));
+        poll().await?;
+        assert_eq!(sink.take_events(), reply(ListingEvent::Restock, 3));
+        // Kept for a restock to reply to
+        poll().await?;
+        assert_eq!(sink.take_events(), reply(ListingEvent::SoldOut, 0));
+        assert_eq!(messages.read().await["1001"].quantity, 0);
+
+        // Past the restock window the message goes
+        let later = Utc::now() + RESTOCK_WINDOW;
+        ChannelMonitor::remove_sold_out(
+            sink.as_ref(),
+            &bot_db,
+            channel(),
+            messages.clone(),
+            &config,
+            later,
+        )
+        .await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }]
+        );
+        assert!(messages.read().await.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_listing_isnt_sold_out() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_appears"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.notify.push(ListingEvent::SoldOut);
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let update = async |items| {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                items,
+                &config,
+            )
+            .await
+        };
+
+        update(source.get_items(&config).await?).await?;
+        sink.take_events();
+        // Bags left but the purchase period is over, no sold out notice nor restock to wait for
+        let mut items = source.get_items(&config).await?;
+        items[0].purchase_end = Some(Utc::now() - chrono::Duration::minutes(1));
+        update(items).await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }]
+        );
+        assert!(messages.read().await.is_empty());
+        // Nor does it count as a sell-out in the history and stats
+        assert_eq!(bot_db.get_last_sold_out(channel(), "1001").await?, None);
+        let changes = bot_db
+            .get_quantity_changes(channel(), DateTime::UNIX_EPOCH, None)
+            .await?;
+        assert!(changes.last().is_some_and(|c| c.quantity == 0 && c.ended));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_digest_resumes_after_restart() -> anyhow::Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_sold_out"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.digest = Some(DigestSort::Distance);
+        let subscription = Subscription {
+            user_id: UserId::new(5),
+            filter: None,
+        };
+        bot_db.set_subscription(channel(), &subscription).await?;
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async |quantities: &mut HashMap<String, usize>| {
+            ChannelMonitor::update_digest(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                quantities,
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll(&mut HashMap::new()).await?;
+        sink.wait_direct_messages(1).await;
+        assert!(sink.take_events().contains(&SinkEvent::DirectMessage {
+            user_id: UserId::new(5),
+            item_id: "1001".to_owned(),
+        }));
+        // Sold out while the bot was down, the next run picks up the open listing to close it
+        let mut quantities = bot_db.get_open_quantities(channel()).await?;
+        poll(&mut quantities).await?;
+        let history = bot_db
+            .get_history(channel(), DateTime::UNIX_EPOCH, None)
+            .await?;
+        assert_eq!(history.len(), 1);
+        assert!(history[0].sold_out.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_digest_posted_again_only_when_gone() -> anyhow::Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let sink = RecordingSink::default();
+        ChannelMonitor::publish_digest(&sink, &bot_db, channel(), vec![]).await?;
+        sink.take_events();
+
+        // A temporary failure leaves the page alone for the next poll
+        sink.then_fail(anyhow::anyhow!("Internal server error"));
+        assert!(
+            ChannelMonitor::publish_digest(&sink, &bot_db, channel(), vec![])
+                .await
+                .is_err()
+        );
+        assert!(sink.take_events().is_empty());
+        assert_eq!(
+            bot_db.get_digest_messages(channel()).await?,
+            vec![MessageId::new(1)]
+        );
+
+        sink.then_fail(SinkError::UnknownMessage);
+        ChannelMonitor::publish_digest(&sink, &bot_db, channel(), vec![]).await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::PostDigest {
+                channel_id: channel(),
+                message_id: MessageId::new(2),
+                item_ids: vec![],
+            }]
+        );
+        assert_eq!(
+            bot_db.get_digest_messages(channel()).await?,
+            vec![MessageId::new(2)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_digest_replaces_listing_messages() -> anyhow::Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let source = FakeSource::from_fixtures(&["listings", "listing_sold_out", "listings"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.digest = Some(DigestSort::Price);
+        // Message of the listings mode before switching to the digest, restocked since
+        let item_message = ItemMessage {
+            message_id: MessageId::new(7),
+            quantity: 1,
+            thread_id: None,
+        };
+        bot_db
+            .set_item_message(channel(), "1001", &item_message)
+            .await?;
+        let messages = Arc::new(RwLock::new(bot_db.get_item_messages(channel()).await?));
+        let mut quantities = HashMap::new();
+        let mut poll = async |config: &TGTGConfig| {
+            ChannelMonitor::update_digest(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                &mut quantities,
+                source.get_items(config).await?,
+                config,
+            )
+            .await
+        };
+
+        // The sushi is sold out, only the bakery makes it to the digest
+        poll(&config).await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![
+                SinkEvent::Delete {
+                    channel_id: channel(),
+                    message_id: MessageId::new(7),
+                },
+                SinkEvent::PostDigest {
+                    channel_id: channel(),
+                    message_id: MessageId::new(1),
+                    item_ids: vec!["1001".to_owned()],
+                },
+            ]
+        );
+        assert!(messages.read().await.is_empty());
+        assert!(bot_db.get_item_messages(channel()).await?.is_empty());
+
+        poll(&config).await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::EditDigest {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+                item_ids: vec![],
+            }]
+        );
+        let history = bot_db
+            .get_history(channel(), DateTime::UNIX_EPOCH, None)
+            .await?;
+        assert!(history[0].sold_out.is_some());
+
+        // Back to one message per listing, the digest goes away
+        ChannelMonitor::update_location(
+            sink.clone(),
+            &bot_db,
+            channel(),
+            messages.clone(),
+            source.get_items(&config).await?,
+            &TGTGConfig::new(52.3676, 4.9041),
+        )
+        .await?;
+        let events = sink.take_events();
+        assert_eq!(
+            events[0],
+            SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }
+        );
+        assert!(matches!(events[1], SinkEvent::Post { .. }));
+        assert!(bot_db.get_digest_messages(channel()).await?.is_empty());
+        Ok(())
+    }
+
+    /// Monitor fed by a running scheduler, returned once it posted its first listing.
+    async fn posting_monitor(
+        sink: Arc<RecordingSink>,
+        bot_db: Arc<BotDB>,
+    ) -> anyhow::Result<(ChannelMonitor, JoinHandle<()>)> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listing_appears"])?);
+        let scheduler = Arc::new(Scheduler::new(source));
+        let scheduler_handle = scheduler.spawn();
+        let monitor = ChannelMonitor::init(
+            sink,
+            bot_db,
+            channel(),
+            scheduler,
+            TGTGConfig::new_with_radius(52.3551, 4.8921, 1),
+        );
+        while monitor.messages.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok((monitor, scheduler_handle))
+    }
+
+    #[tokio::test]
+    async fn test_stop_deletes_messages() -> anyhow::Result<()> {
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = Arc::new(BotDB::in_memory().await?);
+        let (monitor, scheduler_handle) = posting_monitor(sink.clone(), bot_db.clone()).await?;
+        monitor.stop().await;
+        scheduler_handle.abort();
+        let events = sink.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }
+        );
+        assert!(bot_db.get_item_messages(channel()).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drop_keeps_messages() -> anyhow::Result<()> {
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = Arc::new(BotDB::in_memory().await?);
+        let (monitor, scheduler_handle) = posting_monitor(sink.clone(), bot_db.clone()).await?;
+        drop(monitor);
+        scheduler_handle.abort();
+        assert_eq!(sink.take_events().len(), 1);
+        // Handed over to the monitor of the next run
+        let persisted = bot_db.get_item_messages(channel()).await?;
+        assert_eq!(persisted["1001"].message_id, MessageId::new(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reposts_deleted_message() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_quantity_changed"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll().await?;
+        sink.take_events();
+        // Deleted by a moderator, the edit fails
+        sink.then_fail(SinkError::UnknownMessage);
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Post {
+                channel_id: channel(),
+                message_id: MessageId::new(2),
+                item_id: "1001".to_owned(),
+                quantity: 1,
+                mention: None,
+            }]
+        );
+        let persisted = bot_db.get_item_messages(channel()).await?;
+        assert_eq!(persisted["1001"].message_id, MessageId::new(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deletes_listings_left_out_by_new_filters() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listings", "listings"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let update = async |items| {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                items,
+                &config,
+            )
+            .await
+        };
+
+        update(source.get_items(&config).await?).await?;
+        assert_eq!(sink.take_events().len(), 1);
+        // The scheduler leaves out the bakery after a filter change, the sushi stays listed
+        let mut items = source.get_items(&config).await?;
+        items.retain(|i| i.item.item_id != "1001");
+        update(items).await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Delete {
+                channel_id: channel(),
+                message_id: MessageId::new(1),
+            }]
+        );
+        assert!(messages.read().await.is_empty());
+        assert!(bot_db.get_item_messages(channel()).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_listing_is_unavailable() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listing_appears"])?;
+        let mut listing = source.get_items(&TGTGConfig::new(52.3676, 4.9041)).await?[0].clone();
+        let now = Utc::now();
+        assert!(is_available(&listing, now));
+        listing.purchase_end = Some(now - chrono::Duration::minutes(1));
+        assert!(!is_available(&listing, now));
+        listing.purchase_end = None;
+        assert!(!is_available(&listing, now));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restart_reconciles_persisted_messages() -> anyhow::Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        // Messages left behind by a crashed run, one of them for an item which is gone
+        for (item_id, message_id) in [("1001", 7), ("2002", 8)] {
+            let item_message = ItemMessage {
+                message_id: MessageId::new(message_id),
+                quantity: 3,
+                thread_id: None,
+            };
+            bot_db
+                .set_item_message(channel(), item_id, &item_message)
+                .await?;
+        }
+        let source = FakeSource::from_fixtures(&["listing_quantity_changed"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let messages = Arc::new(RwLock::new(bot_db.get_item_messages(channel()).await?));
+        ChannelMonitor::update_location(
+            sink.clone(),
+            &bot_db,
+            channel(),
+            messages.clone(),
+            source.get_items(&config).await?,
+            &config,
+        )
+        .await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![
+                SinkEvent::Edit {
+                    channel_id: channel(),
+                    message_id: MessageId::new(7),
+                    item_id: "1001".to_owned(),
+                    quantity: 1,
+                },
+                SinkEvent::Delete {
+                    channel_id: channel(),
+                    message_id: MessageId::new(8),
+                },
+            ]
+        );
+        let persisted = bot_db.get_item_messages(channel()).await?;
+        assert_eq!(persisted.len(), 1);
+        assert_eq!(persisted["1001"].quantity, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_heads_up_before_usual_release() -> anyhow::Result<()> {
+        let bot_db = BotDB::in_memory().await?;
+        let source = FakeSource::from_fixtures(&["listing_appears"])?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        let listing = source.get_items(&config).await?.remove(0);
+        let at = |s| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        // Released at 17:00 in Amsterdam two fridays in a row
+        for (released, sold_out) in [
+            ("2026-07-03T15:00:00Z", "2026-07-03T15:20:00Z"),
+            ("2026-07-10T15:00:00Z", "2026-07-10T15:30:00Z"),
+        ] {
+            bot_db
+                .record_quantity(channel(), &listing, at(released))
+                .await?;
+            bot_db
+                .record_closed(channel(), "1001", at(sold_out), true)
+                .await?;
+        }
+        let scheduler = Scheduler::new(Arc::new(FakeSource::new(Vec::<String>::new())));
+        let sink = RecordingSink::default();
+        let mut announced = HashSet::new();
+        let announce = async |announced: &mut _, now| {
+            ChannelMonitor::announce_releases(
+                &sink,
+                &bot_db,
+                &scheduler,
+                channel(),
+                announced,
+                at(now),
+            )
+            .await
+        };
+
+        let _receiver = scheduler.subscribe(channel(), config.clone());
+        announce(&mut announced, "2026-07-17T14:55:00Z").await?;
+        assert_eq!(sink.take_events(), vec![]);
+
+        config.predictions = true;
+        scheduler.reconfigure(channel(), config);
+        announce(&mut announced, "2026-07-17T14:55:00Z").await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::HeadsUp {
+                channel_id: channel(),
+                store_name: listing.store.store_name.clone(),
+            }]
+        );
+        // Announced once per release
+        announce(&mut announced, "2026-07-17T14:56:00Z").await?;
+        assert_eq!(sink.take_events(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_threads_per_store() -> anyhow::Result<()> {
+        let source =
+            FakeSource::from_fixtures(&["listing_appears", "listing_sold_out", "listing_appears"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.threads = true;
+        config.notify = vec![ListingEvent::New];
+        let messages = Arc::new(RwLock::new(HashMap::new()));
+        let poll = async || {
+            ChannelMonitor::update_location(
+                sink.clone(),
+                &bot_db,
+                channel(),
+                messages.clone(),
+                source.get_items(&config).await?,
+                &config,
+            )
+            .await
+        };
+
+        poll().await?;
+        let thread_id = ChannelId::new(1);
+        assert_eq!(
+            sink.take_events(),
+            vec![
+                SinkEvent::Thread {
+                    channel_id: channel(),
+                    thread_id,
+                    name: "Bakery Zuid".to_owned(),
+                },
+                SinkEvent::Post {
+                    channel_id: thread_id,
+                    message_id: MessageId::new(2),
+                    item_id: "1001".to_owned(),
+                    quantity: 3,
+                    mention: None,
+                },
+            ]
+        );
+        let persisted = bot_db.get_item_messages(channel()).await?;
+        assert_eq!(persisted["1001"].thread_id, Some(thread_id));
+
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Delete {
+                channel_id: thread_id,
+                message_id: MessageId::new(2),
+            }]
+        );
+
+        // The store keeps its thread
+        poll().await?;
+        assert_eq!(
+            sink.take_events(),
+            vec![SinkEvent::Post {
+                channel_id: thread_id,
+                message_id: MessageId::new(3),
+                item_id: "1001".to_owned(),
+                quantity: 3,
+                mention: None,
+            }]
+        );
+        assert_eq!(
+            bot_db.get_store_thread(channel(), "501").await?,
+            Some(thread_id)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_thread_started_again_once_gone() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listing_appears", "listing_appears"])?;
+        let sink = Arc::new(RecordingSink::default());
+        let bot_db = BotDB::in_memory().await?;
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.threads = true;
+        let listing = source.get_items(&config).await?.remove(0);
+        let post = async || {
+            ChannelMonitor::post_listing(sink.as_ref(), &bot_db, channel(), &listing, None, &config)
+                .await
+        };
+        bot_db
+            .set_store_thread(channel(), "501", ChannelId::new(77))
+            .await?;
+
+        // A hiccup of discord keeps the thread for the next try
+        sink.then_fail(anyhow::anyhow!("Internal Server Error"));
+        assert!(post().await.is_err());
+        assert_eq!(
+            bot_db.get_store_thread(channel(), "501").await?,
+            Some(ChannelId::new(77))
+        );
+
+        sink.then_fail(SinkError::UnavailableChannel);
+        let (_, thread_id) = post().await?;
+        assert!(matches!(
+            &sink.take_events()[..],
+            [SinkEvent::Thread { .. }, SinkEvent::Post { .. }]
+        ));
+        assert_ne!(thread_id, Some(ChannelId::new(77)));
+        assert_eq!(bot_db.get_store_thread(channel(), "501").await?, thread_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context as _, bail};
+use chrono::{DateTime, NaiveTime, Utc};
+
+use crate::data::TGTGListing;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Day of a pickup, relative to today in the store's time zone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum PickupDay {
+    #[name = "today"]
+    Today,
+    #[name = "not tomorrow"]
+    NotTomorrow,
+}
+
+/// Pickups shown in a channel, by their local hours and day in the store's time zone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickupFilter {
+    /// Hours the pickup has to overlap with, on the day it starts
+    pub hours: Option<(NaiveTime, NaiveTime)>,
+    pub day: Option<PickupDay>,
+}
+
+impl PickupFilter {
+    /// Whether the pickup of the listing passes at `now`, listings without a pickup don't
+    pub fn matches(&self, listing: &TGTGListing, now: DateTime<Utc>) -> bool {
+        let Some(interval) = &listing.pickup_interval else {
+            return false;
+        };
+        let timezone = listing.store.store_time_zone;
+        let start = interval.start.with_timezone(&timezone).naive_local();
+        let end = interval.end.with_timezone(&timezone).naive_local();
+        let today = now.with_timezone(&timezone).date_naive();
+        let day = match self.day {
+            None => true,
+            Some(PickupDay::Today) => start.date() == today,
+            Some(PickupDay::NotTomorrow) => today.succ_opt() != Some(start.date()),
+        };
+        day && self.hours.is_none_or(|(from, until)| {
+            start < start.date().and_time(until) && end > start.date().and_time(from)
+        })
+    }
+}
+
+/// Formats as `17:00-21:00 today`, the same format `from_str` reads.
+impl fmt::Display for PickupFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some((from, until)) = self.hours {
+            parts.push(format!(
+                "{}-{}",
+                from.format(TIME_FORMAT),
+                until.format(TIME_FORMAT)
+            ));
+        }
+        match self.day {
+            Some(PickupDay::Today) => parts.push("today".to_owned()),
+            Some(PickupDay::NotTomorrow) => parts.push("not-tomorrow".to_owned()),
+            None => {}
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl FromStr for PickupFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self {
+            hours: None,
+            day: None,
+        };
+        for part in s.split_whitespace() {
+            match part {
+                "today" => filter.day = Some(PickupDay::Today),
+                "not-tomorrow" => filter.day = Some(PickupDay::NotTomorrow),
+                hours => filter.hours = Some(parse_hours(hours)?),
+            }
+        }
+        if filter.hours.is_none() && filter.day.is_none() {
+            bail!("Pickup filter should look like 17:00-21:00 today");
+        }
+        Ok(filter)
+    }
+}
+
+/// Reads hours like `17:00-21:00`, ending after they start.
+pub fn parse_hours(hours: &str) -> anyhow::Result<(NaiveTime, NaiveTime)> {
+    let (from, until) = hours
+        .split_once('-')
+        .context("Hours should look like 17:00-21:00")?;
+    let from = NaiveTime::parse_from_str(from, TIME_FORMAT)?;
+    let until = NaiveTime::parse_from_str(until, TIME_FORMAT)?;
+    if until <= from {
+        bail!("Pickup hours should end after they start");
+    }
+    Ok((from, until))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::TGTGConfig;
+    use crate::source::{FakeSource, ListingSource};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_and_display() -> anyhow::Result<()> {
+        let filter = "17:00-21:00 today".parse::<PickupFilter>()?;
+        assert_eq!(filter.day, Some(PickupDay::Today));
+        assert_eq!(filter.to_string(), "17:00-21:00 today");
+        let filter = "not-tomorrow".parse::<PickupFilter>()?;
+        assert_eq!(filter.hours, None);
+        assert_eq!(filter.to_string().parse::<PickupFilter>()?, filter);
+        assert!("21:00-17:00".parse::<PickupFilter>().is_err());
+        assert!("17:00".parse::<PickupFilter>().is_err());
+        assert!("".parse::<PickupFilter>().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matches() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&["listings"])?;
+        let items = source.get_items(&TGTGConfig::new(52.3676, 4.9041)).await?;
+        // Pickup of the bakery from 18:00 to 18:30 in Amsterdam on 2026-10-16
+        let bakery = &items[0];
+        let morning = utc("2026-10-16T08:00:00Z");
+        let filter = |s: &str| s.parse::<PickupFilter>().unwrap();
+        assert!(filter("17:00-21:00 today").matches(bakery, morning));
+        assert!(filter("18:15-19:00").matches(bakery, morning));
+        assert!(!filter("12:00-18:00").matches(bakery, morning));
+        // The day before, the pickup is tomorrow
+        let day_before = utc("2026-10-15T08:00:00Z");
+        assert!(!filter("today").matches(bakery, day_before));
+        assert!(!filter("not-tomorrow").matches(bakery, day_before));
+        assert!(filter("not-tomorrow").matches(bakery, morning));
+        // The sushi has no pickup interval
+        assert!(!filter("not-tomorrow").matches(&items[1], morning));
+        Ok(())
+    }
+}
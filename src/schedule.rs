@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context as _, bail};
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Active hours of a channel on the given weekdays, in the channel's time zone. A window
+/// ending before it starts runs past midnight into the next day.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Bit `n` is set when the window opens on the `n`th day from Monday
+    weekdays: u8,
+    pub timezone: Tz,
+}
+
+impl Schedule {
+    pub fn new(start: NaiveTime, end: NaiveTime, weekdays: &[Weekday], timezone: Tz) -> Self {
+        Self {
+            start,
+            end,
+            weekdays: weekdays
+                .iter()
+                .fold(0, |mask, day| mask | 1 << day.num_days_from_monday()),
+            timezone,
+        }
+    }
+
+    fn opens_on(&self, weekday: Weekday) -> bool {
+        self.weekdays & 1 << weekday.num_days_from_monday() != 0
+    }
+
+    /// Whether polling is allowed at the given time
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone);
+        let time = local.time();
+        if self.start <= self.end {
+            self.opens_on(local.weekday()) && self.start <= time && time < self.end
+        } else {
+            (self.opens_on(local.weekday()) && self.start <= time)
+                || (self.opens_on(local.weekday().pred()) && time < self.end)
+        }
+    }
+
+    /// Start of the window active at `at`, or of the next one
+    pub fn next_activation(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_active(at) {
+            return Some(at);
+        }
+        let today = at.with_timezone(&self.timezone).date_naive();
+        (0..=7)
+            .filter_map(|days| today.checked_add_days(Days::new(days)))
+            .filter(|date| self.opens_on(date.weekday()))
+            .filter_map(|date| {
+                // A start skipped by a DST change falls back to the end of the gap
+                self.timezone
+                    .from_local_datetime(&date.and_time(self.start))
+                    .earliest()
+                    .or_else(|| {
+                        let shifted = date.and_time(self.start) + chrono::Duration::hours(1);
+                        self.timezone.from_local_datetime(&shifted).earliest()
+                    })
+            })
+            .map(|start| start.with_timezone(&Utc))
+            .find(|start| *start > at)
+    }
+}
+
+/// Formats as `08:00-22:00 mon,tue,wed Europe/Amsterdam`, the same format `from_str` reads.
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let weekdays = (0..7)
+            .filter_map(|n| Weekday::try_from(n).ok())
+            .filter(|day| self.opens_on(*day))
+            .map(|day| day.to_string().to_lowercase())
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{}-{} {} {}",
+            self.start.format(TIME_FORMAT),
+            self.end.format(TIME_FORMAT),
+            weekdays.join(","),
+            self.timezone
+        )
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let (Some(hours), Some(days), Some(timezone), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Schedule should look like 08:00-22:00 mon-fri Europe/Amsterdam");
+        };
+        let (start, end) = hours
+            .split_once('-')
+            .context("Hours should look like 08:00-22:00")?;
+        let (start, end) = (
+            NaiveTime::parse_from_str(start, TIME_FORMAT)?,
+            NaiveTime::parse_from_str(end, TIME_FORMAT)?,
+        );
+        // The window would never contain a time and the channel would never be checked
+        if start == end {
+            bail!("Hours can't start and end at the same time");
+        }
+        let timezone = timezone
+            .parse::<Tz>()
+            .map_err(|_| anyhow::anyhow!("Unknown time zone {}", timezone))?;
+        Ok(Self::new(start, end, &parse_weekdays(days)?, timezone))
+    }
+}
+
+/// Reads `daily` or a comma separated list of days and day ranges like `mon-fri,sun`.
+pub fn parse_weekdays(days: &str) -> anyhow::Result<Vec<Weekday>> {
+    if days.eq_ignore_ascii_case("daily") {
+        return Ok((0..7).filter_map(|n| Weekday::try_from(n).ok()).collect());
+    }
+    let parse_day = |day: &str| {
+        day.parse::<Weekday>()
+            .map_err(|_| anyhow::anyhow!("Unknown weekday {}", day))
+    };
+    let mut weekdays = Vec::new();
+    for part in days.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (parse_day(first)?, parse_day(last)?);
+                weekdays.push(day);
+                while day != last {
+                    day = day.succ();
+                    weekdays.push(day);
+                }
+            }
+            None => weekdays.push(parse_day(part)?),
+        }
+    }
+    Ok(weekdays)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_and_display() -> anyhow::Result<()> {
+        let schedule = "08:00-22:00 mon-wed,sat Europe/Amsterdam".parse::<Schedule>()?;
+        assert_eq!(
+            schedule.to_string(),
+            "08:00-22:00 mon,tue,wed,sat Europe/Amsterdam"
+        );
+        assert_eq!(schedule.to_string().parse::<Schedule>()?, schedule);
+        assert!("08:00-22:00 someday UTC".parse::<Schedule>().is_err());
+        assert!("08:00 daily UTC".parse::<Schedule>().is_err());
+        assert!("08:00-08:00 daily UTC".parse::<Schedule>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_active_in_local_time() -> anyhow::Result<()> {
+        let schedule = "17:00-21:00 mon-fri Europe/Amsterdam".parse::<Schedule>()?;
+        // Friday 16:30 UTC is 18:30 in Amsterdam summer time
+        assert!(schedule.is_active(utc("2026-07-03T16:30:00Z")));
+        assert!(!schedule.is_active(utc("2026-07-03T19:30:00Z")));
+        // Saturday
+        assert!(!schedule.is_active(utc("2026-07-04T16:30:00Z")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_overnight_window() -> anyhow::Result<()> {
+        let schedule = "22:00-02:00 fri UTC".parse::<Schedule>()?;
+        assert!(schedule.is_active(utc("2026-07-03T23:00:00Z")));
+        // Saturday night belongs to the friday window
+        assert!(schedule.is_active(utc("2026-07-04T01:00:00Z")));
+        assert!(!schedule.is_active(utc("2026-07-04T23:00:00Z")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_activation() -> anyhow::Result<()> {
+        let schedule = "17:00-21:00 mon-fri Europe/Amsterdam".parse::<Schedule>()?;
+        let active = utc("2026-07-03T16:30:00Z");
+        assert_eq!(schedule.next_activation(active), Some(active));
+        // Friday night waits until monday 17:00 in Amsterdam
+        assert_eq!(
+            schedule.next_activation(utc("2026-07-03T20:00:00Z")),
+            Some(utc("2026-07-06T15:00:00Z"))
+        );
+        let never = Schedule::new(schedule.start, schedule.end, &[], Tz::UTC);
+        assert_eq!(never.next_activation(active), None);
+        Ok(())
+    }
+}
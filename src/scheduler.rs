@@ -0,0 +1,824 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use serenity::model::id::ChannelId;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::data::{MAX_INTERVAL, MIN_INTERVAL, TGTGConfig, TGTGListing};
+use crate::sink::ListingSink;
+use crate::source::ListingSource;
+use crate::tgtg::TGTGError;
+
+/// Fraction of the interval by which each poll is moved randomly, spreading out the requests
+const JITTER: f64 = 0.1;
+const BACKOFF_BASE: Duration = Duration::from_secs(30);
+const BACKOFF_MAX: Duration = Duration::from_secs(30 * 60);
+/// Consecutive failures after which the circuit breaker trips, like a blocking error
+const BREAKER_THRESHOLD: u32 = 6;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+const BREAKER_COOLDOWN_MAX: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Central poller fetching TGTG listings once for every group of overlapping channel
+/// locations and fanning the results out to the subscribed channel monitors.
+pub struct Scheduler {
+    listing_source: Arc<dyn ListingSource>,
+    subscribers: Mutex<HashMap<ChannelId, Subscriber>>,
+    backoff: Mutex<Backoff>,
+    admin: Option<(Arc<dyn ListingSink>, ChannelId)>,
+    wake: Notify,
+}
+
+/// Error state shared by all channels, TGTG blocks per account and not per location.
+#[derive(Default)]
+struct Backoff {
+    failures: u32,
+    paused_until: Option<Instant>,
+    /// Cooldown of the circuit breaker while it's open
+    breaker: Option<Duration>,
+    /// Whether the last failure was TGTG rejecting the credentials, new ones end the pause
+    unauthorized: bool,
+}
+
+struct Subscriber {
+    config: TGTGConfig,
+    sender: mpsc::Sender<Vec<TGTGListing>>,
+    next_poll: Instant,
+    /// Polls happen every `MIN_INTERVAL` until then, e.g. around a predicted release
+    boosted_until: Option<Instant>,
+}
+
+impl Subscriber {
+    /// Polling interval in seconds at the given time, shortened while boosted
+    fn interval(&self, at: Instant) -> u64 {
+        match self.boosted_until {
+            Some(until) if until > at => self.config.interval.min(MIN_INTERVAL),
+            _ => self.config.interval,
+        }
+    }
+}
+
+/// Subscriber taking part in a round of polls, with a copy of its config and sender.
+struct Candidate {
+    channel_id: ChannelId,
+    config: TGTGConfig,
+    sender: mpsc::Sender<Vec<TGTGListing>>,
+    due: bool,
+}
+
+/// Watched areas sharing a single fetch, all of them lie within the leader's.
+struct Group {
+    leader: TGTGConfig,
+    members: Vec<(ChannelId, TGTGConfig)>,
+}
+
+impl Scheduler {
+    pub fn new(listing_source: Arc<dyn ListingSource>) -> Self {
+        Self {
+            listing_source,
+            subscribers: Mutex::new(HashMap::new()),
+            backoff: Mutex::new(Backoff::default()),
+            admin: None,
+            wake: Notify::new(),
+        }
+    }
+
+    /// Same as `new`, with alerts about a blocked account sent to the admin channel.
+    pub fn new_with_admin(
+        listing_source: Arc<dyn ListingSource>,
+        sink: Arc<dyn ListingSink>,
+        admin_channel: ChannelId,
+    ) -> Self {
+        Self {
+            admin: Some((sink, admin_channel)),
+            ..Self::new(listing_source)
+        }
+    }
+
+    /// Spawns the polling loop.
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                scheduler.poll_due().await;
+                let next_poll = scheduler.next_poll();
+                tokio::select! {
+                    _ = async {
+                        match next_poll {
+                            Some(at) => tokio::time::sleep_until(at).await,
+                            None => std::future::pending().await,
+                        }
+                    } => {},
+                    _ = scheduler.wake.notified() => {},
+                }
+            }
+        })
+    }
+
+    /// Registers a channel, its first poll happens right away or once its schedule opens.
+    pub fn subscribe(
+        &self,
+        channel_id: ChannelId,
+        config: TGTGConfig,
+    ) -> mpsc::Receiver<Vec<TGTGListing>> {
+        let (sender, receiver) = mpsc::channel(1);
+        self.subscribers.lock().unwrap().insert(
+            channel_id,
+            Subscriber {
+                next_poll: within_schedule(&config, Instant::now()),
+                config,
+                sender,
+                boosted_until: None,
+            },
+        );
+        self.wake.notify_one();
+        receiver
+    }
+
+    /// Replaces the configuration of a subscribed channel, its next poll moves to match
+    /// the new interval and schedule.
+    pub fn reconfigure(&self, channel_id: ChannelId, config: TGTGConfig) {
+        if let Some(subscriber) = self.subscribers.lock().unwrap().get_mut(&channel_id) {
+            subscriber.config = config;
+            let now = Instant::now();
+            let next_poll = later(now, jittered(subscriber.interval(now)));
+            subscriber.next_poll =
+                within_schedule(&subscriber.config, subscriber.next_poll.min(next_poll));
+        }
+        self.wake.notify_one();
+    }
+
+    /// Current configuration of a subscribed channel.
+    pub fn config(&self, channel_id: ChannelId) -> Option<TGTGConfig> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .get(&channel_id)
+            .map(|s| s.config.clone())
+    }
+
+    /// Polls the channel every `MIN_INTERVAL` until the given time, starting right away.
+    pub fn boost(&self, channel_id: ChannelId, until: Instant) {
+        if let Some(subscriber) = self.subscribers.lock().unwrap().get_mut(&channel_id) {
+            subscriber.boosted_until =
+                Some(subscriber.boosted_until.map_or(until, |b| b.max(until)));
+            let now = Instant::now();
+            let next_poll = later(now, jittered(subscriber.interval(now)));
+            subscriber.next_poll =
+                within_schedule(&subscriber.config, subscriber.next_poll.min(next_poll));
+        }
+        self.wake.notify_one();
+    }
+
+    /// Resumes polling right away when it was paused because TGTG rejected the previous
+    /// credentials, e.g. after a login or a token refresh.
+    pub async fn credentials_changed(&self) {
+        let recovered = {
+            let mut backoff = self.backoff.lock().unwrap();
+            if !backoff.unauthorized {
+                return;
+            }
+            let recovered = backoff.breaker.is_some();
+            *backoff = Backoff::default();
+            recovered
+        };
+        info!("TGTG credentials changed, requests are resumed");
+        if recovered {
+            self.alert("TGTG credentials changed, monitors are resumed.")
+                .await;
+        }
+        self.wake.notify_one();
+    }
+
+    pub fn unsubscribe(&self, channel_id: ChannelId) {
+        self.subscribers.lock().unwrap().remove(&channel_id);
+    }
+
+    fn next_poll(&self) -> Option<Instant> {
+        let next_poll = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.next_poll)
+            .min()?;
+        let paused_until = self.backoff.lock().unwrap().paused_until;
+        Some(paused_until.map_or(next_poll, |p| p.max(next_poll)))
+    }
+
+    /// Subscribers which are due or within their schedule, the latter can share the fetch
+    /// of a due one.
+    fn candidates(&self, now: Instant, wall_clock: DateTime<Utc>) -> Vec<Candidate> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(channel_id, s)| Candidate {
+                channel_id: *channel_id,
+                config: s.config.clone(),
+                sender: s.sender.clone(),
+                due: s.next_poll <= now,
+            })
+            .filter(|c| {
+                c.due
+                    || c.config
+                        .schedule
+                        .as_ref()
+                        .is_none_or(|schedule| schedule.is_active(wall_clock))
+            })
+            .collect()
+    }
+
+    /// Groups every watched area of the given channels under the largest area covering
+    /// it. Identical areas end up in the same group.
+    fn groups(channels: &[Candidate]) -> Vec<Group> {
+        let mut areas = channels
+            .iter()
+            .flat_map(|c| {
+                c.config
+                    .areas()
+                    .into_iter()
+                    .map(|area| (c.channel_id, area))
+            })
+            .collect::<Vec<_>>();
+        areas.sort_by_key(|(_, area)| std::cmp::Reverse(area.radius));
+        let mut groups: Vec<Group> = Vec::new();
+        for (channel_id, area) in areas {
+            match groups.iter_mut().find(|g| g.leader.covers(&area)) {
+                Some(group) => group.members.push((channel_id, area)),
+                None => groups.push(Group {
+                    leader: area.clone(),
+                    members: vec![(channel_id, area)],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// Fetches every group with an area of a due channel. All channels with an area in a
+    /// fetched group receive the listings of all their watches at once, de-duplicated, and
+    /// are polled again after their own interval, so that overlapping channels keep sharing
+    /// their fetches. Nothing is fetched while backing off from errors.
+    pub async fn poll_due(&self) {
+        let now = Instant::now();
+        if self
+            .backoff
+            .lock()
+            .unwrap()
+            .paused_until
+            .is_some_and(|p| p > now)
+        {
+            return;
+        }
+        let wall_clock = Utc::now();
+        let candidates = self.candidates(now, wall_clock);
+        let mut groups = Scheduler::groups(&candidates);
+        // A channel needs every group with one of its areas fetched, which may pull in more
+        // channels and so more groups
+        let mut polled = candidates
+            .iter()
+            .filter(|c| c.due)
+            .map(|c| c.channel_id)
+            .collect::<HashSet<_>>();
+        let mut fetched = Vec::new();
+        while let Some(index) = groups
+            .iter()
+            .position(|g| g.members.iter().any(|m| polled.contains(&m.0)))
+        {
+            let group = groups.swap_remove(index);
+            polled.extend(group.members.iter().map(|m| m.0));
+            fetched.push(group);
+        }
+        let mut found: HashMap<ChannelId, HashMap<String, TGTGListing>> = HashMap::new();
+        for group in fetched {
+            let channels = group.members.iter().map(|m| m.0).collect::<Vec<_>>();
+            let items = match self.listing_source.get_items(&group.leader).await {
+                Ok(items) => {
+                    self.on_success().await;
+                    items
+                }
+                Err(why) => {
+                    warn!(
+                        "Channels {:?}: Failed to update location with {}",
+                        channels, why
+                    );
+                    // Stop the round, the channels stay due for after the pause
+                    self.on_failure(&why).await;
+                    return;
+                }
+            };
+            info!(
+                "Channels {:?}: Scheduler fetched {} items",
+                channels,
+                items.len()
+            );
+            for (channel_id, area) in group.members {
+                let found = found.entry(channel_id).or_default();
+                for item in items_matching(&area, &items, wall_clock) {
+                    // An item seen by several watches is kept once, closest to its watch
+                    if found
+                        .get(&item.item.item_id)
+                        .is_none_or(|seen| item.distance < seen.distance)
+                    {
+                        found.insert(item.item.item_id.clone(), item);
+                    }
+                }
+            }
+        }
+        for candidate in candidates {
+            let channel_id = candidate.channel_id;
+            if !polled.contains(&channel_id) {
+                continue;
+            }
+            if let Some(subscriber) = self.subscribers.lock().unwrap().get_mut(&channel_id) {
+                let interval = subscriber.interval(now);
+                subscriber.next_poll =
+                    within_schedule(&candidate.config, later(now, jittered(interval)));
+            }
+            let mut items = found
+                .remove(&channel_id)
+                .map(|items| items.into_values().collect::<Vec<_>>())
+                .unwrap_or_default();
+            items.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            if candidate.sender.try_send(items).is_err() {
+                warn!(
+                    "Channel {}: Monitor is busy, skipping the fetched items",
+                    channel_id
+                );
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    async fn on_success(&self) {
+        let recovered = {
+            let mut backoff = self.backoff.lock().unwrap();
+            let recovered = backoff.breaker.is_some();
+            *backoff = Backoff::default();
+            recovered
+        };
+        if recovered {
+            self.alert("TGTG requests work again, monitors are resumed.")
+                .await;
+        }
+    }
+
+    async fn on_failure(&self, why: &anyhow::Error) {
+        let tgtg_error = why.downcast_ref::<TGTGError>();
+        let (tripped, pause) = {
+            let mut backoff = self.backoff.lock().unwrap();
+            backoff.failures += 1;
+            backoff.unauthorized = matches!(tgtg_error, Some(TGTGError::Unauthorized));
+            let blocking = tgtg_error.is_some_and(TGTGError::is_blocking);
+            let pause = if blocking || backoff.failures >= BREAKER_THRESHOLD {
+                // Open the breaker, doubling its cooldown if it fails again right after
+                let cooldown = backoff
+                    .breaker
+                    .map_or(BREAKER_COOLDOWN, |c| (c * 2).min(BREAKER_COOLDOWN_MAX));
+                backoff.breaker = Some(cooldown);
+                cooldown
+            } else {
+                let retry_after = match tgtg_error {
+                    Some(TGTGError::RateLimited { retry_after }) => *retry_after,
+                    _ => None,
+                };
+                backoff_delay(backoff.failures).max(retry_after.unwrap_or_default())
+            };
+            backoff.paused_until = Some(later(Instant::now(), pause));
+            (backoff.breaker.is_some(), pause)
+        };
+        warn!("TGTG requests paused for {} seconds", pause.as_secs());
+        if tripped {
+            self.alert(&format!(
+                "TGTG requests keep failing ({}). All monitors are paused for {} minutes.",
+                why,
+                pause.as_secs() / 60
+            ))
+            .await;
+        }
+    }
+
+    async fn alert(&self, content: &str) {
+        if let Some((sink, channel_id)) = &self.admin
+            && let Err(why) = sink.notice(*channel_id, content).await
+        {
+            warn!("Channel {}: Failed to send alert with {}", channel_id, why);
+        }
+    }
+}
+
+/// Exponential backoff with equal jitter, half of the delay is randomized.
+fn backoff_delay(failures: u32) -> Duration {
+    let delay = BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(BACKOFF_MAX);
+    delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+}
+
+/// Polling interval in seconds moved randomly by up to `JITTER` in either direction. Longer
+/// intervals than `MAX_INTERVAL`, e.g. from a bad row, are cut to it.
+fn jittered(interval: u64) -> Duration {
+    let interval = Duration::from_secs(interval.min(MAX_INTERVAL));
+    let factor = 1.0 + rand::random_range(-JITTER..=JITTER);
+    Duration::try_from_secs_f64(interval.as_secs_f64() * factor).unwrap_or(interval)
+}
+
+/// Instant `delay` after `at`, a day after it if that would overflow.
+fn later(at: Instant, delay: Duration) -> Instant {
+    at.checked_add(delay)
+        .unwrap_or_else(|| at + Duration::from_secs(MAX_INTERVAL))
+}
+
+/// Moves a poll planned at `at` to the next opening of the channel's schedule if needed.
+fn within_schedule(config: &TGTGConfig, at: Instant) -> Instant {
+    let Some(schedule) = &config.schedule else {
+        return at;
+    };
+    let wall_clock = Utc::now() + at.saturating_duration_since(Instant::now());
+    match schedule.next_activation(wall_clock) {
+        Some(activation) => later(at, (activation - wall_clock).to_std().unwrap_or_default()),
+        // A schedule without weekdays never opens, check again in a day
+        None => later(at, Duration::from_secs(24 * 60 * 60)),
+    }
+}
+
+/// Keeps the listings matching the watched area, with distances relative to it.
+fn items_matching(
+    area: &TGTGConfig,
+    items: &[TGTGListing],
+    now: DateTime<Utc>,
+) -> Vec<TGTGListing> {
+    items
+        .iter()
+        .filter(|i| area.matches(i, now))
+        .map(|i| {
+            let location = &i.pickup_location.location;
+            TGTGListing {
+                distance: area.distance_to(location.latitude, location.longitude),
+                ..i.clone()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::{DEFAULT_INTERVAL, PriceFilter, StoreFilter, Watch};
+    use crate::sink::{RecordingSink, SinkEvent};
+    use crate::source::FakeSource;
+
+    #[tokio::test]
+    async fn test_overlapping_locations_share_a_fetch() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        // City wide channel and a channel around the bakery, within the city circle
+        let mut city = scheduler.subscribe(
+            ChannelId::new(1),
+            TGTGConfig::new_with_radius(52.3676, 4.9041, 3),
+        );
+        let mut bakery = scheduler.subscribe(
+            ChannelId::new(2),
+            TGTGConfig::new_with_radius(52.3551, 4.8921, 1),
+        );
+
+        scheduler.poll_due().await;
+        assert_eq!(source.requests(), vec![(52.3676, 4.9041, 3)]);
+
+        let city_items = city.try_recv()?;
+        assert_eq!(city_items.len(), 2);
+        let bakery_items = bakery.try_recv()?;
+        assert_eq!(bakery_items.len(), 1);
+        assert_eq!(bakery_items[0].item.item_id, "1001");
+        assert!(bakery_items[0].distance < 0.01);
+
+        // Nothing is due until the interval passes
+        scheduler.poll_due().await;
+        assert_eq!(source.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_channel_not_due_shares_a_fetch() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings", "listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut city = scheduler.subscribe(
+            ChannelId::new(1),
+            TGTGConfig::new_with_radius(52.3676, 4.9041, 3),
+        );
+        let mut bakery = scheduler.subscribe(
+            ChannelId::new(2),
+            TGTGConfig::new_with_radius(52.3551, 4.8921, 1),
+        );
+        scheduler.poll_due().await;
+        city.try_recv()?;
+        bakery.try_recv()?;
+
+        // Only the bakery channel is due, as the jitter moved their polls apart
+        let now = Instant::now();
+        for (channel_id, subscriber) in scheduler.subscribers.lock().unwrap().iter_mut() {
+            subscriber.next_poll = match channel_id.get() {
+                2 => now,
+                _ => now + Duration::from_secs(30),
+            };
+        }
+        scheduler.poll_due().await;
+        assert_eq!(source.requests().len(), 2);
+        assert_eq!(city.try_recv()?.len(), 2);
+        assert_eq!(bakery.try_recv()?.len(), 1);
+        // Both are polled again together after their interval
+        assert!(
+            scheduler
+                .subscribers
+                .lock()
+                .unwrap()
+                .values()
+                .all(|s| s.next_poll > now + Duration::from_secs(30))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disjoint_locations_fetch_separately() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings", "listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let _first = scheduler.subscribe(
+            ChannelId::new(1),
+            TGTGConfig::new_with_radius(52.3676, 4.9041, 1),
+        );
+        let _second = scheduler.subscribe(
+            ChannelId::new(2),
+            TGTGConfig::new_with_radius(52.3551, 4.8921, 1),
+        );
+        scheduler.poll_due().await;
+        assert_eq!(source.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watches_are_merged_without_duplicates() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut config = TGTGConfig::new(52.3551, 4.8921);
+        let watch = |name: &str, latitude, longitude, radius| Watch {
+            name: name.to_owned(),
+            latitude,
+            longitude,
+            radius,
+            filter: None,
+        };
+        config.watches = vec![
+            watch("sushi", 52.3789, 4.9003, 1),
+            watch("city", 52.3676, 4.9041, 3),
+        ];
+        let mut channel = scheduler.subscribe(ChannelId::new(1), config);
+
+        scheduler.poll_due().await;
+        // The city watch covers the other areas, a single fetch serves all of them
+        assert_eq!(source.requests(), vec![(52.3676, 4.9041, 3)]);
+        let items = channel.try_recv()?;
+        assert_eq!(items.len(), 2);
+        // Distances are relative to the closest watch, the main location and sushi
+        for item_id in ["1001", "1002"] {
+            let item = items.iter().find(|i| i.item.item_id == item_id).unwrap();
+            assert!(item.distance < 0.01);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expression_filter() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let filter = r#"name ~ "sushi" and distance < 3"#.parse()?;
+        let mut channel = scheduler.subscribe(
+            ChannelId::new(1),
+            TGTGConfig::new_full(52.3676, 4.9041, 3, filter),
+        );
+        scheduler.poll_due().await;
+        let items = channel.try_recv()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item.item_id, "1002");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_price_filter() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut config = TGTGConfig::new_with_radius(52.3676, 4.9041, 3);
+        // Inclusive bound, the bakery costs exactly 3.99 EUR
+        config.price = Some(PriceFilter {
+            min: None,
+            max: Some(3.99),
+            currency: Some("eur".to_owned()),
+        });
+        let mut cheap = scheduler.subscribe(ChannelId::new(1), config.clone());
+        config.price = Some(PriceFilter {
+            min: Some(1.0),
+            max: None,
+            currency: Some("GBP".to_owned()),
+        });
+        let mut pounds = scheduler.subscribe(ChannelId::new(2), config);
+        scheduler.poll_due().await;
+        let items = cheap.try_recv()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item.item_id, "1001");
+        assert!(pounds.try_recv()?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_filter() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut config = TGTGConfig::new_with_radius(52.3676, 4.9041, 3);
+        let filter = |store_id: &str, allowed| StoreFilter {
+            store_id: store_id.to_owned(),
+            store_name: String::new(),
+            allowed,
+        };
+        config.stores = vec![filter("501", false)];
+        let mut blocked = scheduler.subscribe(ChannelId::new(1), config.clone());
+        // Allowing a store hides the ones which aren't allowed
+        config.stores = vec![filter("501", true)];
+        let mut allowed = scheduler.subscribe(ChannelId::new(2), config);
+        scheduler.poll_due().await;
+        let items = blocked.try_recv()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item.item_id, "1002");
+        let items = allowed.try_recv()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item.item_id, "1001");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_captcha_opens_the_breaker() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::new(Vec::<String>::new()).then_error(TGTGError::Captcha));
+        let sink = Arc::new(RecordingSink::default());
+        let admin_channel = ChannelId::new(9);
+        let scheduler = Scheduler::new_with_admin(source.clone(), sink.clone(), admin_channel);
+        let _city = scheduler.subscribe(ChannelId::new(1), TGTGConfig::new(52.3676, 4.9041));
+
+        scheduler.poll_due().await;
+        assert!(matches!(
+            &sink.take_events()[..],
+            [SinkEvent::Notice { channel_id, .. }] if *channel_id == admin_channel
+        ));
+        assert!(scheduler.next_poll().unwrap() >= Instant::now() + BREAKER_COOLDOWN / 2);
+
+        // Paused, even though the subscriber is due again
+        scheduler
+            .subscribers
+            .lock()
+            .unwrap()
+            .get_mut(&ChannelId::new(1))
+            .unwrap()
+            .next_poll = Instant::now();
+        scheduler.poll_due().await;
+        assert_eq!(source.requests().len(), 1);
+
+        // The first success after the pause closes the breaker
+        scheduler.on_success().await;
+        assert_eq!(sink.take_events().len(), 1);
+        assert!(scheduler.backoff.lock().unwrap().breaker.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_new_credentials_close_the_breaker() -> anyhow::Result<()> {
+        let source =
+            Arc::new(FakeSource::new(Vec::<String>::new()).then_error(TGTGError::Unauthorized));
+        let scheduler = Scheduler::new(source.clone());
+        let _city = scheduler.subscribe(ChannelId::new(1), TGTGConfig::new(52.3676, 4.9041));
+        scheduler.poll_due().await;
+        assert!(scheduler.next_poll().unwrap() >= Instant::now() + BREAKER_COOLDOWN / 2);
+
+        // The channel is still due from the failed round, it's polled right away
+        scheduler.credentials_changed().await;
+        assert!(scheduler.next_poll().unwrap() <= Instant::now());
+        assert!(scheduler.backoff.lock().unwrap().breaker.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_new_credentials_keep_a_captcha_pause() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::new(Vec::<String>::new()).then_error(TGTGError::Captcha));
+        let scheduler = Scheduler::new(source.clone());
+        let _city = scheduler.subscribe(ChannelId::new(1), TGTGConfig::new(52.3676, 4.9041));
+        scheduler.poll_due().await;
+        scheduler.credentials_changed().await;
+        assert!(scheduler.next_poll().unwrap() >= Instant::now() + BREAKER_COOLDOWN / 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_next_poll_follows_channel_interval() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings", "listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut busy = TGTGConfig::new(52.3676, 4.9041);
+        busy.interval = 30;
+        let mut quiet = TGTGConfig::new(52.3551, 4.8921);
+        quiet.interval = 300;
+        let _busy = scheduler.subscribe(ChannelId::new(1), busy);
+        let _quiet = scheduler.subscribe(ChannelId::new(2), quiet);
+        let now = Instant::now();
+        scheduler.poll_due().await;
+
+        let subscribers = scheduler.subscribers.lock().unwrap();
+        for (channel_id, interval) in [(1, 30), (2, 300)] {
+            let next_poll = subscribers[&ChannelId::new(channel_id)].next_poll - now;
+            let interval = Duration::from_secs(interval);
+            assert!(next_poll >= interval.mul_f64(1.0 - JITTER));
+            assert!(next_poll <= interval.mul_f64(1.0 + JITTER) + Duration::from_secs(1));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_boost_polls_more_often() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings", "listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.interval = 300;
+        let _receiver = scheduler.subscribe(ChannelId::new(1), config);
+        scheduler.poll_due().await;
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait > Duration::from_secs(MIN_INTERVAL * 2));
+
+        let until = Instant::now() + Duration::from_secs(600);
+        scheduler.boost(ChannelId::new(1), until);
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait <= Duration::from_secs(MIN_INTERVAL).mul_f64(1.0 + JITTER));
+        // Still boosted after the next poll
+        scheduler
+            .subscribers
+            .lock()
+            .unwrap()
+            .get_mut(&ChannelId::new(1))
+            .unwrap()
+            .next_poll = Instant::now();
+        scheduler.poll_due().await;
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait <= Duration::from_secs(MIN_INTERVAL).mul_f64(1.0 + JITTER));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_closed_schedule_delays_polling() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        // Window opening in two hours, whatever the time is now
+        let opens = Utc::now() + chrono::Duration::hours(2);
+        let schedule = format!(
+            "{}-{} daily UTC",
+            opens.format("%H:%M"),
+            (opens + chrono::Duration::hours(1)).format("%H:%M")
+        );
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.schedule = Some(schedule.parse()?);
+        let _night = scheduler.subscribe(ChannelId::new(1), config);
+
+        scheduler.poll_due().await;
+        assert!(source.requests().is_empty());
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait > Duration::from_secs(60 * 60) && wait <= Duration::from_secs(2 * 60 * 60));
+
+        // Clearing the schedule polls again within the interval
+        scheduler.reconfigure(ChannelId::new(1), TGTGConfig::new(52.3676, 4.9041));
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait <= Duration::from_secs(DEFAULT_INTERVAL).mul_f64(1.0 + JITTER));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_huge_interval_is_capped() -> anyhow::Result<()> {
+        let source = Arc::new(FakeSource::from_fixtures(&["listings"])?);
+        let scheduler = Scheduler::new(source.clone());
+        let mut config = TGTGConfig::new(52.3676, 4.9041);
+        config.interval = u64::MAX;
+        let _receiver = scheduler.subscribe(ChannelId::new(1), config);
+        scheduler.poll_due().await;
+        let wait = scheduler.next_poll().unwrap() - Instant::now();
+        assert!(wait <= Duration::from_secs(MAX_INTERVAL).mul_f64(1.0 + JITTER));
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff_delay_grows() {
+        for failures in 1..10 {
+            let delay = backoff_delay(failures);
+            let full = BACKOFF_BASE
+                .saturating_mul(2u32.pow(failures - 1))
+                .min(BACKOFF_MAX);
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, MessageId, RoleId, UserId};
+
+use crate::data::{DigestPage, ListingEvent, TGTGListing};
+use crate::stats::ReleasePrediction;
+
+/// Destination of the listing messages maintained by the monitors.
+#[async_trait]
+pub trait ListingSink: Send + Sync {
+    /// Posts a new listing message, mentioning the role if given, and returns its id.
+    async fn post(
+        &self,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+        mention: Option<RoleId>,
+    ) -> anyhow::Result<MessageId>;
+
+    /// Replaces the content of a posted listing message.
+    async fn edit(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        listing: &TGTGListing,
+    ) -> anyhow::Result<()>;
+
+    /// Replies to a posted listing message about a change of the listing, mentioning the
+    /// role if given, and returns the id of the reply.
+    async fn reply(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        listing: &TGTGListing,
+        event: ListingEvent,
+        mention: Option<RoleId>,
+    ) -> anyhow::Result<MessageId>;
+
+    /// Removes a posted listing message.
+    async fn delete(&self, channel_id: ChannelId, message_id: MessageId) -> anyhow::Result<()>;
+
+    /// Posts and pins a page of the summary of a channel in digest mode, returns its id.
+    async fn post_digest(
+        &self,
+        channel_id: ChannelId,
+        page: &DigestPage,
+    ) -> anyhow::Result<MessageId>;
+
+    /// Replaces the content of a posted summary page.
+    async fn edit_digest(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        page: &DigestPage,
+    ) -> anyhow::Result<()>;
+
+    /// Starts a public thread in the channel, e.g. for the listings of a store, and returns
+    /// its id.
+    async fn create_thread(&self, channel_id: ChannelId, name: &str) -> anyhow::Result<ChannelId>;
+
+    /// Posts a plain text notice, e.g. an alert to the admin channel.
+    async fn notice(&self, channel_id: ChannelId, content: &str) -> anyhow::Result<MessageId>;
+
+    /// Warns the channel about a store which usually releases bags soon.
+    async fn heads_up(
+        &self,
+        channel_id: ChannelId,
+        prediction: &ReleasePrediction,
+    ) -> anyhow::Result<MessageId>;
+
+    /// Sends the listing found in a channel to a subscribed user.
+    async fn direct_message(
+        &self,
+        user_id: UserId,
+        channel_id: ChannelId,
+        listing: &TGTGListing,
+    ) -> anyhow::Result<()>;
+}
+
+/// Failures of a sink the monitors recover from, other errors are passed through as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// The message doesn't exist anymore, e.g. it was deleted by hand
+    UnknownMessage,
+    /// The channel or thread doesn't exist anymore, or the thread is archived or locked
+    UnavailableChannel,
+}
+
+impl SinkError {
+    /// Whether the error is the given sink error.
+    pub fn is(why: &anyhow::Error, error: SinkError) -> bool {
+        why.downcast_ref::<SinkError>() == Some(&error)
+    }
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::UnknownMessage => write!(f, "Message is not found"),
+            SinkError::UnavailableChannel => write!(f, "Channel is not found or closed"),
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+#[cfg(test)]
+pub use recording::{RecordingSink, SinkEvent};
+
+#[cfg(test)]
+mod recording {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use async_trait::async_trait;
+    use poise::serenity_prelude as serenity;
+    use serenity::all::{ChannelId, MessageId, RoleId, UserId};
+    use tokio::sync::Semaphore;
+
+    use crate::data::{DigestPage, ListingEvent, TGTGListing};
+    use crate::stats::ReleasePrediction;
+
+    use super::ListingSink;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum SinkEvent {
+        Post {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            item_id: String,
+            quantity: usize,
+            mention: Option<RoleId>,
+        },
+        Edit {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            item_id: String,
+            quantity: usize,
+        },
+        Reply {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            item_id: String,
+            event: ListingEvent,
+            mention: Option<RoleId>,
+        },
+        Delete {
+            channel_id: ChannelId,
+            message_id: MessageId,
+        },
+        PostDigest {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            item_ids: Vec<String>,
+        },
+        EditDigest {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            item_ids: Vec<String>,
+        },
+        Thread {
+            channel_id: ChannelId,
+            thread_id: ChannelId,
+            name: String,
+        },
+        Notice {
+            channel_id: ChannelId,
+            content: String,
+        },
+        HeadsUp {
+            channel_id: ChannelId,
+            store_name: String,
+        },
+        DirectMessage {
+            user_id: UserId,
+            item_id: String,
+        },
+    }
+
+    /// In-memory sink recording every call, message ids are handed out sequentially from 1.
+    pub struct RecordingSink {
+        events: Mutex<Vec<SinkEvent>>,
+        last_message_id: AtomicU64,
+        /// Outcomes of the next calls, `None` lets a call succeed
+        failures: Mutex<VecDeque<Option<anyhow::Error>>>,
+        /// A permit per direct message sent, as they go out in a task of their own
+        direct_messages: Semaphore,
+    }
+
+    impl Default for RecordingSink {
+        fn default() -> Self {
+            Self {
+                events: Mutex::default(),
+                last_message_id: AtomicU64::default(),
+                failures: Mutex::default(),
+                direct_messages: Semaphore::new(0),
+            }
+        }
+    }
+
+    impl RecordingSink {
+        /// Returns the recorded events since the last call.
+        pub fn take_events(&self) -> Vec<SinkEvent> {
+            std::mem::take(&mut self.events.lock().unwrap())
+        }
+
+        /// Makes the next post, edit, reply, delete or digest page fail with the error, without
+        /// recording it.
+        pub fn then_fail(&self, error: impl Into<anyhow::Error>) {
+            self.failures.lock().unwrap().push_back(Some(error.into()));
+        }
+
+        /// Lets the next call succeed, so that a failure queued after it hits a later call.
+        pub fn then_succeed(&self) {
+            self.failures.lock().unwrap().push_back(None);
+        }
+
+        /// Waits until the given number of direct messages were sent since the last wait.
+        pub async fn wait_direct_messages(&self, count: u32) {
+            self.direct_messages
+                .acquire_many(count)
+                .await
+                .expect("Semaphore is never closed")
+                .forget();
+        }
+
+        fn failure(&self) -> anyhow::Result<()> {
+            match self.failures.lock().unwrap().pop_front().flatten() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            }
+        }
+
+        fn record(&self, event: SinkEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn next_message_id(&self) -> MessageId {
+            MessageId::new(self.last_message_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    }
+
+    fn item_ids(page: &DigestPage) -> Vec<String> {
+        page.listings
+            .iter()
+            .map(|l| l.item.item_id.clone())
+            .collect()
+    }
+
+    #[async_trait]
+    impl ListingSink for RecordingSink {
+        async fn post(
+            &self,
+            channel_id: ChannelId,
+            listing: &TGTGListing,
+            mention: Option<RoleId>,
+        ) -> anyhow::Result<MessageId> {
+            self.failure()?;
+            let message_id = self.next_message_id();
+            self.record(SinkEvent::Post {
+                channel_id,
+                message_id,
+                item_id: listing.item.item_id.clone(),
+                quantity: listing.items_available,
+                mention,
+            });
+            Ok(message_id)
+        }
+
+        async fn edit(
+            &self,
+            channel_id: ChannelId,
+            message_id: MessageId,
+            listing: &TGTGListing,
+        ) -> anyhow::Result<()> {
+            self.failure()?;
+            self.record(SinkEvent::Edit {
+                channel_id,
+                message_id,
+                item_id: listing.item.item_id.clone(),
+                quantity: listing.items_available,
+            });
+            Ok(())
+        }
+
+        async fn reply(
+            &self,
+            channel_id: ChannelId,
+            message_id: MessageId,
+            listing: &TGTGListing,
+            event: ListingEvent,
+            mention: Option<RoleId>,
+        ) -> anyhow::Result<MessageId> {
+            self.failure()?;
+            let reply_id = self.next_message_id();
+            self.record(SinkEvent::Reply {
+                channel_id,
+                message_id,
+                item_id: listing.item.item_id.clone(),
+                event,
+                mention,
+            });
+            Ok(reply_id)
+        }
+
+        async fn delete(&self, channel_id: ChannelId, message_id: MessageId) -> anyhow::Result<()> {
+            self.failure()?;
+            self.record(SinkEvent::Delete {
+                channel_id,
+                message_id,
+            });
+            Ok(())
+        }
+
+        async fn post_digest(
+            &self,
+            channel_id: ChannelId,
+            page: &DigestPage,
+        ) -> anyhow::Result<MessageId> {
+            self.failure()?;
+            let message_id = self.next_message_id();
+            self.record(SinkEvent::PostDigest {
+                channel_id,
+                message_id,
+                item_ids: item_ids(page),
+            });
+            Ok(message_id)
+        }
+
+        async fn edit_digest(
+            &self,
+            channel_id: ChannelId,
+            message_id: MessageId,
+            page: &DigestPage,
+        ) -> anyhow::Result<()> {
+            self.failure()?;
+            self.record(SinkEvent::EditDigest {
+                channel_id,
+                message_id,
+                item_ids: item_ids(page),
+            });
+            Ok(())
+        }
+
+        async fn create_thread(
+            &self,
+            channel_id: ChannelId,
+            name: &str,
+        ) -> anyhow::Result<ChannelId> {
+            // Threads are channels, their ids come from the same sequence as the messages
+            let thread_id = ChannelId::new(self.next_message_id().get());
+            self.record(SinkEvent::Thread {
+                channel_id,
+                thread_id,
+                name: name.to_owned(),
+            });
+            Ok(thread_id)
+        }
+
+        async fn notice(&self, channel_id: ChannelId, content: &str) -> anyhow::Result<MessageId> {
+            let message_id = self.next_message_id();
+            self.record(SinkEvent::Notice {
+                channel_id,
+                content: content.to_owned(),
+            });
+            Ok(message_id)
+        }
+
+        async fn heads_up(
+            &self,
+            channel_id: ChannelId,
+            prediction: &ReleasePrediction,
+        ) -> anyhow::Result<MessageId> {
+            let message_id = self.next_message_id();
+            self.record(SinkEvent::HeadsUp {
+                channel_id,
+                store_name: prediction.store_name.clone(),
+            });
+            Ok(message_id)
+        }
+
+        async fn direct_message(
+            &self,
+            user_id: UserId,
+            _channel_id: ChannelId,
+            listing: &TGTGListing,
+        ) -> anyhow::Result<()> {
+            self.record(SinkEvent::DirectMessage {
+                user_id,
+                item_id: listing.item.item_id.clone(),
+            });
+            self.direct_messages.add_permits(1);
+            Ok(())
+        }
+    }
+}
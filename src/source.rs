@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+
+use crate::data::{TGTGConfig, TGTGListing};
+
+/// Provider of TGTG listings for a configured location.
+#[async_trait]
+pub trait ListingSource: Send + Sync {
+    /// Fetches every listing around the configured location.
+    async fn get_items(&self, config: &TGTGConfig) -> anyhow::Result<Vec<TGTGListing>>;
+}
+
+#[cfg(test)]
+pub use fake::FakeSource;
+
+#[cfg(test)]
+mod fake {
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use crate::data::{TGTGConfig, TGTGListing};
+    use crate::tgtg::TGTGError;
+
+    use super::ListingSource;
+
+    /// Listing source replaying recorded JSON responses, one per `get_items` call.
+    pub struct FakeSource {
+        responses: Mutex<VecDeque<Result<String, TGTGError>>>,
+        requests: Mutex<Vec<(f64, f64, u8)>>,
+    }
+
+    impl FakeSource {
+        pub fn new<I, S>(responses: I) -> Self
+        where
+            I: IntoIterator<Item = S>,
+            S: Into<String>,
+        {
+            Self {
+                responses: Mutex::new(responses.into_iter().map(|r| Ok(r.into())).collect()),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Appends a failing call to the script.
+        pub fn then_error(self, error: TGTGError) -> Self {
+            self.responses.lock().unwrap().push_back(Err(error));
+            self
+        }
+
+        /// Latitude, longitude and radius of every `get_items` call so far.
+        pub fn requests(&self) -> Vec<(f64, f64, u8)> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        /// Loads the responses from `tests/fixtures/<name>.json` files in the given order.
+        pub fn from_fixtures(names: &[&str]) -> anyhow::Result<Self> {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+            let responses = names
+                .iter()
+                .map(|name| std::fs::read_to_string(dir.join(format!("{}.json", name))))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Self::new(responses))
+        }
+    }
+
+    #[async_trait]
+    impl ListingSource for FakeSource {
+        async fn get_items(&self, config: &TGTGConfig) -> anyhow::Result<Vec<TGTGListing>> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((config.latitude, config.longitude, config.radius));
+            let response = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| anyhow::anyhow!("No recorded response left"))??;
+            Ok(serde_json::from_str(&response)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake_source_replays_in_order() -> anyhow::Result<()> {
+        let source = FakeSource::from_fixtures(&[
+            "listing_appears",
+            "listing_quantity_changed",
+            "listing_sold_out",
+        ])?;
+        let config = TGTGConfig::new(52.3676, 4.9041);
+        let quantities = [3, 1, 0];
+        for quantity in quantities {
+            let items = source.get_items(&config).await?;
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].item.item_id, "1001");
+            assert_eq!(items[0].items_available, quantity);
+        }
+        assert!(source.get_items(&config).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::data::QuantityChange;
+
+/// Release and sell-out figures of a store, computed from its quantity changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreStats {
+    pub store_name: String,
+    /// Times listings went from sold out to available
+    pub releases: usize,
+    /// Median local release time per weekday, Monday first
+    pub release_times: Vec<(Weekday, NaiveTime)>,
+    /// Median time between a release and selling out, releases still available don't count
+    pub median_sellout: Option<Duration>,
+    /// Highest quantity of a release on average
+    pub average_quantity: f64,
+    /// Price of a release on average, in major units of `currency`
+    pub average_price: f64,
+    pub currency: String,
+}
+
+/// A listing from being released until it sold out.
+struct Release {
+    weekday: Weekday,
+    local_time: NaiveTime,
+    timezone: Tz,
+    duration: Option<Duration>,
+    quantity: usize,
+    price: f64,
+    currency: String,
+}
+
+/// Computes the stats of every store appearing in the changes, ordered by release count.
+/// The changes have to be ordered by observation time.
+pub fn store_stats(changes: &[QuantityChange]) -> Vec<StoreStats> {
+    let mut stats = releases_by_store(changes)
+        .into_iter()
+        .map(|(_, (store_name, releases))| {
+            let mut times: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+            for r in releases.iter() {
+                times
+                    .entry(r.weekday.num_days_from_monday())
+                    .or_default()
+                    .push(r.local_time.num_seconds_from_midnight());
+            }
+            let release_times = times
+                .into_iter()
+                .filter_map(|(day, mut seconds)| {
+                    let weekday = Weekday::try_from(day as u8).ok()?;
+                    let time =
+                        NaiveTime::from_num_seconds_from_midnight_opt(median(&mut seconds)?, 0)?;
+                    Some((weekday, time))
+                })
+                .collect();
+            let mut durations = releases
+                .iter()
+                .filter_map(|r| r.duration.map(|d| d.num_seconds()))
+                .collect::<Vec<_>>();
+            let count = releases.len() as f64;
+            StoreStats {
+                store_name: store_name.to_owned(),
+                releases: releases.len(),
+                release_times,
+                median_sellout: median(&mut durations).map(Duration::seconds),
+                average_quantity: releases.iter().map(|r| r.quantity as f64).sum::<f64>() / count,
+                average_price: releases.iter().map(|r| r.price).sum::<f64>() / count,
+                currency: releases[0].currency.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| {
+        b.releases
+            .cmp(&a.releases)
+            .then_with(|| a.store_name.cmp(&b.store_name))
+    });
+    stats
+}
+
+/// Releases on the same weekday needed before a store's release time is trusted
+const MIN_RELEASES: usize = 2;
+/// How far from the usual time a release still counts towards it
+const RELEASE_SPREAD: Duration = Duration::minutes(30);
+
+/// Release of a store expected soon, going by its past releases on the same weekday.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleasePrediction {
+    /// Id of the store, or its name for releases recorded before stores had ids
+    pub store_id: String,
+    pub store_name: String,
+    pub expected: DateTime<Utc>,
+    /// Past releases around the expected time of day
+    pub releases: usize,
+    pub median_sellout: Option<Duration>,
+}
+
+/// Predicts the releases due within `lead` after `now`. A store qualifies when enough of its
+/// releases on today's weekday happened close to their median local time.
+pub fn upcoming_releases(
+    changes: &[QuantityChange],
+    now: DateTime<Utc>,
+    lead: Duration,
+) -> Vec<ReleasePrediction> {
+    let mut predictions = releases_by_store(changes)
+        .into_iter()
+        .filter_map(|(store_id, (store_name, releases))| {
+            let timezone = releases[0].timezone;
+            let today = now.with_timezone(&timezone).date_naive();
+            let weekday = releases
+                .iter()
+                .filter(|r| r.weekday == today.weekday())
+                .collect::<Vec<_>>();
+            let mut seconds = weekday
+                .iter()
+                .map(|r| r.local_time.num_seconds_from_midnight())
+                .collect::<Vec<_>>();
+            let usual = NaiveTime::from_num_seconds_from_midnight_opt(median(&mut seconds)?, 0)?;
+            let around = weekday
+                .iter()
+                .filter(|r| (r.local_time - usual).abs() <= RELEASE_SPREAD)
+                .collect::<Vec<_>>();
+            if around.len() < MIN_RELEASES {
+                return None;
+            }
+            let expected = timezone
+                .from_local_datetime(&today.and_time(usual))
+                .earliest()?
+                .with_timezone(&Utc);
+            if expected <= now || expected > now + lead {
+                return None;
+            }
+            let mut durations = around
+                .iter()
+                .filter_map(|r| r.duration.map(|d| d.num_seconds()))
+                .collect::<Vec<_>>();
+            Some(ReleasePrediction {
+                store_id: store_id.to_owned(),
+                store_name: store_name.to_owned(),
+                expected,
+                releases: around.len(),
+                median_sellout: median(&mut durations).map(Duration::seconds),
+            })
+        })
+        .collect::<Vec<_>>();
+    predictions.sort_by_key(|p| p.expected);
+    predictions
+}
+
+/// Name and releases of every store appearing in the changes, which are ordered by observation
+/// time. Stores are keyed by id, as stores of a chain share their name, or by name for changes
+/// recorded before stores had ids.
+fn releases_by_store(changes: &[QuantityChange]) -> HashMap<&str, (&str, Vec<Release>)> {
+    let mut by_item: HashMap<&str, Vec<&QuantityChange>> = HashMap::new();
+    for change in changes {
+        by_item.entry(&change.item_id).or_default().push(change);
+    }
+    let mut by_store: HashMap<&str, (&str, Vec<Release>)> = HashMap::new();
+    for item_changes in by_item.values() {
+        let mut open: Option<(&QuantityChange, usize)> = None;
+        for change in item_changes {
+            match (&mut open, change.quantity) {
+                (None, 0) => {}
+                (None, quantity) => open = Some((change, quantity)),
+                (Some((_, highest)), quantity) if quantity > 0 => {
+                    *highest = (*highest).max(quantity)
+                }
+                (Some((released, highest)), _) => {
+                    // Ending without selling out tells nothing about how fast it sells
+                    let sold_out = Some(*change).filter(|c| !c.ended);
+                    let release = release(released, *highest, sold_out);
+                    store_releases(&mut by_store, released).push(release);
+                    open = None;
+                }
+            }
+        }
+        if let Some((released, highest)) = open {
+            store_releases(&mut by_store, released).push(release(released, highest, None));
+        }
+    }
+    by_store
+}
+
+/// Releases collected so far for the store of a change
+fn store_releases<'a, 'b>(
+    by_store: &'b mut HashMap<&'a str, (&'a str, Vec<Release>)>,
+    change: &'a QuantityChange,
+) -> &'b mut Vec<Release> {
+    let store = change.store_id.as_deref().unwrap_or(&change.store_name);
+    &mut by_store
+        .entry(store)
+        .or_insert_with(|| (&change.store_name, Vec::new()))
+        .1
+}
+
+fn release(
+    released: &QuantityChange,
+    quantity: usize,
+    sold_out: Option<&QuantityChange>,
+) -> Release {
+    let local = released
+        .observed_at
+        .with_timezone(&released.store_time_zone);
+    Release {
+        weekday: local.weekday(),
+        local_time: local.time(),
+        timezone: released.store_time_zone,
+        duration: sold_out.map(|s| s.observed_at - released.observed_at),
+        quantity,
+        price: released.price.amount(),
+        currency: released.price.code.clone(),
+    }
+}
+
+/// Lower median, good enough for times of day and durations
+fn median<T: Ord + Copy>(values: &mut [T]) -> Option<T> {
+    values.sort_unstable();
+    values.get(values.len().saturating_sub(1) / 2).copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::ItemPrice;
+
+    fn change(item_id: &str, store_name: &str, quantity: usize, at: &str) -> QuantityChange {
+        QuantityChange {
+            item_id: item_id.to_owned(),
+            store_id: Some(format!("store-{}", store_name)),
+            store_name: store_name.to_owned(),
+            store_time_zone: chrono_tz::Europe::Amsterdam,
+            price: ItemPrice {
+                code: "EUR".to_owned(),
+                minor_units: 399,
+                decimals: 2,
+            },
+            quantity,
+            observed_at: DateTime::parse_from_rfc3339(at)
+                .unwrap()
+                .with_timezone(&Utc),
+            ended: false,
+        }
+    }
+
+    #[test]
+    fn test_store_stats() {
+        let changes = vec![
+            // Friday releases at 17:00 and 17:30 Amsterdam time, two weeks in a row
+            change("1001", "Bakery", 3, "2026-07-03T15:00:00Z"),
+            change("1001", "Bakery", 1, "2026-07-03T15:10:00Z"),
+            change("1001", "Bakery", 0, "2026-07-03T15:20:00Z"),
+            change("1001", "Bakery", 5, "2026-07-10T15:30:00Z"),
+            change("1001", "Bakery", 0, "2026-07-10T16:10:00Z"),
+            // Monday release, still available
+            change("1001", "Bakery", 2, "2026-07-13T08:00:00Z"),
+            change("1002", "Sushi", 4, "2026-07-13T19:00:00Z"),
+        ];
+        let stats = store_stats(&changes);
+        assert_eq!(stats.len(), 2);
+        let bakery = &stats[0];
+        assert_eq!(bakery.store_name, "Bakery");
+        assert_eq!(bakery.releases, 3);
+        assert_eq!(
+            bakery.release_times,
+            vec![
+                (Weekday::Mon, NaiveTime::from_hms_opt(10, 0, 0).unwrap()),
+                (Weekday::Fri, NaiveTime::from_hms_opt(17, 0, 0).unwrap()),
+            ]
+        );
+        assert_eq!(bakery.median_sellout, Some(Duration::minutes(20)));
+        assert!((bakery.average_quantity - 10.0 / 3.0).abs() < 1e-9);
+        assert!((bakery.average_price - 3.99).abs() < 1e-9);
+        assert_eq!(stats[1].median_sellout, None);
+    }
+
+    #[test]
+    fn test_ended_release_has_no_sellout() {
+        let mut ended = change("1001", "Bakery", 0, "2026-07-03T18:00:00Z");
+        ended.ended = true;
+        let changes = vec![
+            change("1001", "Bakery", 3, "2026-07-03T15:00:00Z"),
+            ended,
+            change("1001", "Bakery", 2, "2026-07-04T15:00:00Z"),
+            change("1001", "Bakery", 0, "2026-07-04T15:30:00Z"),
+        ];
+        let stats = store_stats(&changes);
+        // Both are releases, only the second one sold out
+        assert_eq!(stats[0].releases, 2);
+        assert_eq!(stats[0].median_sellout, Some(Duration::minutes(30)));
+    }
+
+    #[test]
+    fn test_chain_stores_kept_apart() {
+        let mut other = change("1003", "Bakery", 2, "2026-07-03T16:00:00Z");
+        other.store_id = Some("store-Bakery-Noord".to_owned());
+        let mut unknown = change("1004", "Sushi", 1, "2026-07-03T17:00:00Z");
+        unknown.store_id = None;
+        let changes = vec![
+            change("1001", "Bakery", 3, "2026-07-03T15:00:00Z"),
+            other,
+            unknown,
+            change("1002", "Sushi", 4, "2026-07-03T18:00:00Z"),
+        ];
+        let stats = store_stats(&changes);
+        // Same named stores stay apart, older changes without id go by name
+        let stores = stats
+            .iter()
+            .map(|s| (s.store_name.as_str(), s.releases))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stores,
+            vec![("Bakery", 1), ("Bakery", 1), ("Sushi", 1), ("Sushi", 1)]
+        );
+    }
+
+    #[test]
+    fn test_upcoming_releases() {
+        let changes = vec![
+            // Fridays around 17:00 Amsterdam time
+            change("1001", "Bakery", 3, "2026-07-03T15:00:00Z"),
+            change("1001", "Bakery", 0, "2026-07-03T15:20:00Z"),
+            change("1001", "Bakery", 5, "2026-07-10T15:10:00Z"),
+            change("1001", "Bakery", 0, "2026-07-10T15:50:00Z"),
+            // A single friday release isn't a pattern yet
+            change("1002", "Sushi", 4, "2026-07-10T15:05:00Z"),
+        ];
+        let at = |s| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let lead = Duration::minutes(10);
+        let predictions = upcoming_releases(&changes, at("2026-07-17T14:55:00Z"), lead);
+        assert_eq!(
+            predictions,
+            vec![ReleasePrediction {
+                store_id: "store-Bakery".to_owned(),
+                store_name: "Bakery".to_owned(),
+                expected: at("2026-07-17T15:00:00Z"),
+                releases: 2,
+                median_sellout: Some(Duration::minutes(20)),
+            }]
+        );
+        // Too early, too late and the wrong weekday
+        assert!(upcoming_releases(&changes, at("2026-07-17T14:30:00Z"), lead).is_empty());
+        assert!(upcoming_releases(&changes, at("2026-07-17T15:00:00Z"), lead).is_empty());
+        assert!(upcoming_releases(&changes, at("2026-07-16T14:55:00Z"), lead).is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Global budget of TGTG requests, a token bucket refilled continuously over a minute.
+pub struct RequestBudget {
+    per_minute: u32,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RequestBudget {
+    pub fn per_minute(per_minute: u32) -> Self {
+        let per_minute = per_minute.max(1);
+        Self {
+            per_minute,
+            bucket: Mutex::new(Bucket {
+                tokens: per_minute as f64,
+                updated_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request fits in the budget and takes it.
+    pub async fn acquire(&self) {
+        while let Some(wait) = self.try_acquire(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes a request from the budget or returns how long to wait for the next one.
+    fn try_acquire(&self, now: Instant) -> Option<Duration> {
+        let rate = self.per_minute as f64 / 60.0;
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(self.per_minute as f64);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_budget_refills_over_time() {
+        let budget = RequestBudget::per_minute(2);
+        let now = Instant::now();
+        assert_eq!(budget.try_acquire(now), None);
+        assert_eq!(budget.try_acquire(now), None);
+        let wait = budget.try_acquire(now).unwrap();
+        assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+        assert_eq!(budget.try_acquire(now + Duration::from_secs(30)), None);
+    }
+}
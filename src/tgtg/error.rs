@@ -0,0 +1,104 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::{StatusCode, header};
+
+/// Failures reported by the TGTG API which need a different reaction from the caller.
+#[derive(Debug)]
+pub enum TGTGError {
+    /// Credentials are missing, expired or revoked, a new login is needed
+    Unauthorized,
+    /// Too many requests, TGTG asks to slow down
+    RateLimited { retry_after: Option<Duration> },
+    /// The anti-bot protection asks for a captcha to be solved
+    Captcha,
+    /// Requests are refused, the account or IP address is most likely blocked
+    Forbidden,
+    /// Any other unsuccessful status
+    Status(StatusCode),
+}
+
+impl TGTGError {
+    /// Classifies an unsuccessful TGTG response.
+    pub fn from_response(status: StatusCode, headers: &header::HeaderMap, body: &str) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => TGTGError::Unauthorized,
+            StatusCode::TOO_MANY_REQUESTS => TGTGError::RateLimited {
+                retry_after: headers
+                    .get(header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse().ok())
+                    .map(Duration::from_secs),
+            },
+            StatusCode::FORBIDDEN if body.contains("captcha") => TGTGError::Captcha,
+            StatusCode::FORBIDDEN => TGTGError::Forbidden,
+            status => TGTGError::Status(status),
+        }
+    }
+
+    /// Whether the account can't be used anymore until someone intervenes.
+    pub fn is_blocking(&self) -> bool {
+        matches!(
+            self,
+            TGTGError::Unauthorized | TGTGError::Captcha | TGTGError::Forbidden
+        )
+    }
+}
+
+impl fmt::Display for TGTGError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TGTGError::Unauthorized => {
+                write!(f, "TGTG credentials are missing or rejected, log in again")
+            }
+            TGTGError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "TGTG rate limit reached, retry after {} seconds",
+                retry_after.as_secs()
+            ),
+            TGTGError::RateLimited { retry_after: None } => write!(f, "TGTG rate limit reached"),
+            TGTGError::Captcha => write!(f, "TGTG asks for a captcha"),
+            TGTGError::Forbidden => {
+                write!(f, "TGTG refuses the requests, account might be blocked")
+            }
+            TGTGError::Status(status) => write!(f, "TGTG request failed with status {}", status),
+        }
+    }
+}
+
+impl std::error::Error for TGTGError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        let mut headers = header::HeaderMap::new();
+        assert!(matches!(
+            TGTGError::from_response(
+                StatusCode::FORBIDDEN,
+                &headers,
+                r#"{"url":"https://geo.captcha-delivery.com/captcha/"}"#
+            ),
+            TGTGError::Captcha
+        ));
+        assert!(matches!(
+            TGTGError::from_response(StatusCode::FORBIDDEN, &headers, ""),
+            TGTGError::Forbidden
+        ));
+        headers.insert(header::RETRY_AFTER, "120".parse().unwrap());
+        assert!(matches!(
+            TGTGError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers, ""),
+            TGTGError::RateLimited {
+                retry_after: Some(d)
+            } if d == Duration::from_secs(120)
+        ));
+        assert!(matches!(
+            TGTGError::from_response(StatusCode::BAD_GATEWAY, &headers, ""),
+            TGTGError::Status(StatusCode::BAD_GATEWAY)
+        ));
+    }
+}
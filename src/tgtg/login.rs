@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::Credentials;
+use super::native::{BASE_URL, http_client, session_cookie};
+
+const AUTH_BY_EMAIL_ENDPOINT: &str = "auth/v5/authByEmail";
+const AUTH_POLLING_ENDPOINT: &str = "auth/v5/authByRequestPollingId";
+const DEVICE_TYPE: &str = "ANDROID";
+const MAX_POLLING_TRIES: usize = 24;
+const POLLING_WAIT_TIME: Duration = Duration::from_secs(5);
+
+/// Pending TGTG login, waiting for the user to click the link sent by email.
+pub struct EmailLogin {
+    http: reqwest::Client,
+    email: String,
+    polling_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AuthByEmailRequest<'a> {
+    device_type: &'a str,
+    email: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthByEmailResponse {
+    state: String,
+    polling_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PollingRequest<'a> {
+    device_type: &'a str,
+    email: &'a str,
+    request_polling_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct PollingResponse {
+    access_token: String,
+    refresh_token: String,
+    startup_data: StartupData,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartupData {
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    user_id: String,
+}
+
+impl EmailLogin {
+    /// Asks TGTG to send a login email to the given address.
+    pub async fn start(email: &str) -> anyhow::Result<Self> {
+        let http = http_client()?;
+        let response = http
+            .post(format!("{}{}", BASE_URL, AUTH_BY_EMAIL_ENDPOINT))
+            .json(&AuthByEmailRequest {
+                device_type: DEVICE_TYPE,
+                email,
+            })
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::TOO_MANY_REQUESTS => {
+                anyhow::bail!("Too many login requests, try again later")
+            }
+            status => anyhow::bail!("TGTG login failed with status {}", status),
+        }
+        let first_response: AuthByEmailResponse = response.json().await?;
+        match (first_response.state.as_str(), first_response.polling_id) {
+            ("WAIT", Some(polling_id)) => {
+                info!("TGTG login email sent, waiting for confirmation");
+                Ok(Self {
+                    http,
+                    email: email.to_owned(),
+                    polling_id,
+                })
+            }
+            ("TERMS", _) => anyhow::bail!(
+                "The email {} is not linked to a TGTG account, sign up with it first",
+                email
+            ),
+            (state, _) => anyhow::bail!("Unexpected TGTG login state {}", state),
+        }
+    }
+
+    /// Polls TGTG until the login link is clicked and returns the new credentials.
+    pub async fn wait(&self) -> anyhow::Result<Credentials> {
+        for _ in 0..MAX_POLLING_TRIES {
+            let response = self
+                .http
+                .post(format!("{}{}", BASE_URL, AUTH_POLLING_ENDPOINT))
+                .json(&PollingRequest {
+                    device_type: DEVICE_TYPE,
+                    email: &self.email,
+                    request_polling_id: &self.polling_id,
+                })
+                .send()
+                .await?;
+            match response.status() {
+                StatusCode::ACCEPTED => tokio::time::sleep(POLLING_WAIT_TIME).await,
+                StatusCode::OK => {
+                    let cookie = session_cookie(response.headers()).unwrap_or_default();
+                    let login: PollingResponse = response.json().await?;
+                    info!("TGTG login confirmed");
+                    return Ok(Credentials {
+                        access_token: login.access_token,
+                        refresh_token: login.refresh_token,
+                        user_id: login.startup_data.user.user_id,
+                        cookie,
+                    });
+                }
+                status => anyhow::bail!("TGTG login polling failed with status {}", status),
+            }
+        }
+        anyhow::bail!("TGTG login was not confirmed in time")
+    }
+}
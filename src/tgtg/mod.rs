@@ -0,0 +1,62 @@
+mod budget;
+mod error;
+mod login;
+// The native client stays compiled in with the python backend for the login flow
+#[cfg_attr(feature = "python", allow(dead_code))]
+mod native;
+#[cfg(feature = "python")]
+mod python;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use crate::source::ListingSource;
+
+pub use budget::RequestBudget;
+pub use error::TGTGError;
+pub use login::EmailLogin;
+#[cfg_attr(feature = "python", allow(unused_imports))]
+pub use native::NativeClient;
+#[cfg(feature = "python")]
+pub use python::PythonClient;
+
+/// Common interface of the TGTG API backends.
+///
+/// Listings are fetched through the [`ListingSource`] supertrait so that monitors only
+/// depend on the source abstraction.
+#[async_trait]
+pub trait TGTGClient: ListingSource {
+    /// Subscribes to the credentials, which change whenever the client refreshes its tokens.
+    fn credential_updates(&self) -> watch::Receiver<Credentials>;
+
+    /// Replaces the credentials of the client, e.g. after a new login.
+    ///
+    /// Unlike refreshes, this doesn't notify `credential_updates` subscribers since the
+    /// caller already owns the new credentials.
+    async fn set_credentials(&self, credentials: Credentials) -> anyhow::Result<()>;
+}
+
+/// Secrets needed to talk to the TGTG API on behalf of an account.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub user_id: String,
+    pub cookie: String,
+}
+
+/// Creates the TGTG client for the enabled backend.
+///
+/// The native client is used unless the crate is built with the `python` feature.
+pub fn init_client(
+    credentials: Credentials,
+    budget: Arc<RequestBudget>,
+) -> anyhow::Result<Arc<dyn TGTGClient>> {
+    #[cfg(not(feature = "python"))]
+    let client = NativeClient::new(credentials, budget)?;
+    #[cfg(feature = "python")]
+    let client = PythonClient::new(credentials, budget)?;
+    Ok(Arc::new(client))
+}
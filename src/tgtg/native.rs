@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use reqwest::{StatusCode, header};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, watch};
+use tracing::info;
+
+use crate::data::{TGTGConfig, TGTGListing};
+use crate::source::ListingSource;
+
+use super::{Credentials, RequestBudget, TGTGClient, TGTGError};
+
+pub(super) const BASE_URL: &str = "https://apptoogoodtogo.com/api/";
+const ITEM_ENDPOINT: &str = "item/v8/";
+const REFRESH_ENDPOINT: &str = "token/v1/refresh";
+const USER_AGENT: &str =
+    "TGTG/24.11.0 Dalvik/2.1.0 (Linux; U; Android 14; Pixel 8 Build/UQ1A.240105.004)";
+const ACCESS_TOKEN_LIFETIME: Duration = Duration::from_secs(4 * 60 * 60);
+const PAGE_SIZE: u32 = 100;
+
+/// TGTG client talking to the mobile app API directly over HTTPS.
+pub struct NativeClient {
+    http: reqwest::Client,
+    budget: Arc<RequestBudget>,
+    session: Mutex<Session>,
+    updates: watch::Sender<Credentials>,
+}
+
+struct Session {
+    credentials: Credentials,
+    refreshed_at: Option<Instant>,
+}
+
+#[derive(Debug, Serialize)]
+struct ItemsRequest<'a> {
+    user_id: &'a str,
+    origin: Origin,
+    radius: u8,
+    page_size: u32,
+    page: u32,
+    discover: bool,
+    favorites_only: bool,
+    with_stock_only: bool,
+    hidden_only: bool,
+    we_care_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Origin {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemsResponse {
+    items: Vec<TGTGListing>,
+}
+
+#[derive(Debug, Serialize)]
+struct RefreshRequest<'a> {
+    refresh_token: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshResponse {
+    access_token: String,
+    refresh_token: String,
+}
+
+impl NativeClient {
+    pub fn new(credentials: Credentials, budget: Arc<RequestBudget>) -> anyhow::Result<Self> {
+        let http = http_client()?;
+        let (updates, _) = watch::channel(credentials.clone());
+        Ok(Self {
+            http,
+            budget,
+            session: Mutex::new(Session {
+                credentials,
+                refreshed_at: None,
+            }),
+            updates,
+        })
+    }
+
+    /// Waits for the request budget before posting to the endpoint.
+    async fn post(&self, endpoint: &str, cookie: &str) -> reqwest::RequestBuilder {
+        self.budget.acquire().await;
+        self.http
+            .post(format!("{}{}", BASE_URL, endpoint))
+            .header(header::ACCEPT, "application/json")
+            .header(header::ACCEPT_LANGUAGE, "en-GB")
+            .header(header::COOKIE, cookie)
+    }
+
+    /// Refreshes the access token if it's older than its lifetime or if forced.
+    async fn refresh(&self, session: &mut Session, force: bool) -> anyhow::Result<()> {
+        if !force
+            && session
+                .refreshed_at
+                .is_some_and(|at| at.elapsed() < ACCESS_TOKEN_LIFETIME)
+        {
+            return Ok(());
+        }
+        let response = self
+            .post(REFRESH_ENDPOINT, &session.credentials.cookie)
+            .await
+            .json(&RefreshRequest {
+                refresh_token: &session.credentials.refresh_token,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await.into());
+        }
+        if let Some(cookie) = session_cookie(response.headers()) {
+            session.credentials.cookie = cookie;
+        }
+        let tokens: RefreshResponse = response.json().await?;
+        session.credentials.access_token = tokens.access_token;
+        session.credentials.refresh_token = tokens.refresh_token;
+        session.refreshed_at = Some(Instant::now());
+        self.updates.send_replace(session.credentials.clone());
+        info!("TGTG access token refreshed");
+        Ok(())
+    }
+
+    async fn fetch_items(
+        &self,
+        session: &Session,
+        config: &TGTGConfig,
+    ) -> anyhow::Result<reqwest::Response> {
+        let request = ItemsRequest {
+            user_id: &session.credentials.user_id,
+            origin: Origin {
+                latitude: config.latitude,
+                longitude: config.longitude,
+            },
+            radius: config.radius,
+            page_size: PAGE_SIZE,
+            page: 1,
+            discover: false,
+            favorites_only: false,
+            with_stock_only: false,
+            hidden_only: false,
+            we_care_only: false,
+        };
+        let response = self
+            .post(ITEM_ENDPOINT, &session.credentials.cookie)
+            .await
+            .bearer_auth(&session.credentials.access_token)
+            .json(&request)
+            .send()
+            .await?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl TGTGClient for NativeClient {
+    fn credential_updates(&self) -> watch::Receiver<Credentials> {
+        self.updates.subscribe()
+    }
+
+    async fn set_credentials(&self, credentials: Credentials) -> anyhow::Result<()> {
+        let mut session = self.session.lock().await;
+        session.credentials = credentials;
+        // Tokens coming from a login are fresh, no need to refresh them right away
+        session.refreshed_at = Some(Instant::now());
+        self.updates.send_if_modified(|current| {
+            *current = session.credentials.clone();
+            false
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ListingSource for NativeClient {
+    async fn get_items(&self, config: &TGTGConfig) -> anyhow::Result<Vec<TGTGListing>> {
+        let mut session = self.session.lock().await;
+        if session.credentials.access_token.is_empty() {
+            return Err(TGTGError::Unauthorized.into());
+        }
+        self.refresh(&mut session, false).await?;
+        let mut response = self.fetch_items(&session, config).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            // The access token might have been revoked before its lifetime, retry once
+            self.refresh(&mut session, true).await?;
+            response = self.fetch_items(&session, config).await?;
+        }
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await.into());
+        }
+        let body = response.text().await?;
+        let items: ItemsResponse =
+            serde_json::from_str(&body).context("Unexpected TGTG item response")?;
+        Ok(items.items)
+    }
+}
+
+async fn error_from_response(response: reqwest::Response) -> TGTGError {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.text().await.unwrap_or_default();
+    TGTGError::from_response(status, &headers, &body)
+}
+
+pub(super) fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()
+}
+
+/// Joins the `name=value` pairs of all `Set-Cookie` headers into a `Cookie` header value.
+pub(super) fn session_cookie(headers: &header::HeaderMap) -> Option<String> {
+    let cookies = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    (!cookies.is_empty()).then(|| cookies.join("; "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_items_response() -> anyhow::Result<()> {
+        let body = include_str!("../../tests/fixtures/items.json");
+        let response: ItemsResponse = serde_json::from_str(body)?;
+        assert_eq!(response.items.len(), 2);
+        assert_eq!(response.items[0].item.item_id, "1001");
+        assert_eq!(response.items[1].items_available, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        let mut headers = header::HeaderMap::new();
+        headers.append(
+            header::SET_COOKIE,
+            "datadome=abc; Max-Age=31536000; Path=/".parse().unwrap(),
+        );
+        headers.append(header::SET_COOKIE, "session=xyz; Secure".parse().unwrap());
+        assert_eq!(
+            session_cookie(&headers).as_deref(),
+            Some("datadome=abc; session=xyz")
+        );
+        assert_eq!(session_cookie(&header::HeaderMap::new()), None);
+    }
+}
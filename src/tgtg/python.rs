@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use pyo3::ffi::c_str;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyTuple};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::data::{TGTGConfig, TGTGListing};
+use crate::source::ListingSource;
+
+use super::{Credentials, RequestBudget, TGTGClient};
+
+pub(crate) fn check_python() -> PyResult<()> {
+    Python::attach(|py| {
+        let sys = py.import("sys")?;
+        let version: String = sys.getattr("version")?.extract()?;
+        let locals = [("os", py.import("os")?)].into_py_dict(py)?;
+        let code = c_str!("os.getenv('USER') or os.getenv('USERNAME') or 'Unknown'");
+        let user: String = py.eval(code, None, Some(&locals))?.extract()?;
+        info!(
+            "Python status OK! Running user: {}, Python version: {}",
+            user, version
+        );
+        Ok(())
+    })
+}
+
+/// TGTG client backed by the `tgtg` python package through pyo3.
+pub struct PythonClient {
+    client: Py<PyAny>,
+    fetch_func: Py<PyAny>,
+    budget: Arc<RequestBudget>,
+    updates: watch::Sender<Credentials>,
+}
+
+impl PythonClient {
+    pub fn new(credentials: Credentials, budget: Arc<RequestBudget>) -> PyResult<Self> {
+        check_python()?;
+        let client = init_client(
+            &credentials.access_token,
+            &credentials.refresh_token,
+            &credentials.user_id,
+            &credentials.cookie,
+        )?;
+        let (updates, _) = watch::channel(credentials);
+        Ok(Self {
+            client,
+            fetch_func: init_fetch_func()?,
+            budget,
+            updates,
+        })
+    }
+
+    /// Reads back the credentials, the python client refreshes its tokens internally.
+    fn py_credentials(&self) -> PyResult<Credentials> {
+        Python::attach(|py| {
+            let client = self.client.bind(py);
+            Ok(Credentials {
+                access_token: client.getattr("access_token")?.extract()?,
+                refresh_token: client.getattr("refresh_token")?.extract()?,
+                user_id: client.getattr("user_id")?.extract()?,
+                cookie: client.getattr("cookie")?.extract()?,
+            })
+        })
+    }
+
+    fn py_get_items(&self, config: &TGTGConfig) -> PyResult<String> {
+        Python::attach(|py| {
+            let client = self.client.extract(py)?;
+            let params = PyTuple::new(
+                py,
+                [
+                    format!("{:.5}", config.latitude),
+                    format!("{:.5}", config.longitude),
+                    format!("{}", config.radius),
+                ],
+            )?;
+            let args = PyTuple::new(
+                py,
+                [
+                    client,
+                    params.get_item(0)?,
+                    params.get_item(1)?,
+                    params.get_item(2)?,
+                ],
+            )?;
+            let ret = self.fetch_func.call1(py, args)?;
+            let items = ret.extract::<String>(py)?;
+            Ok(items)
+        })
+    }
+}
+
+#[async_trait]
+impl TGTGClient for PythonClient {
+    fn credential_updates(&self) -> watch::Receiver<Credentials> {
+        self.updates.subscribe()
+    }
+
+    async fn set_credentials(&self, credentials: Credentials) -> anyhow::Result<()> {
+        Python::attach(|py| {
+            let client = self.client.bind(py);
+            client.setattr("access_token", &credentials.access_token)?;
+            client.setattr("refresh_token", &credentials.refresh_token)?;
+            client.setattr("user_id", &credentials.user_id)?;
+            client.setattr("cookie", &credentials.cookie)?;
+            PyResult::Ok(())
+        })?;
+        self.updates.send_if_modified(|current| {
+            *current = credentials;
+            false
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ListingSource for PythonClient {
+    async fn get_items(&self, config: &TGTGConfig) -> anyhow::Result<Vec<TGTGListing>> {
+        self.budget.acquire().await;
+        let py_items = self.py_get_items(config)?;
+        let credentials = self.py_credentials()?;
+        self.updates.send_if_modified(|current| {
+            let modified = *current != credentials;
+            *current = credentials;
+            modified
+        });
+        let items: Vec<TGTGListing> = serde_json::from_str(&py_items)?;
+        Ok(items)
+    }
+}
+
+fn init_client(
+    access_token: &str,
+    refresh_token: &str,
+    user_id: &str,
+    cookie: &str,
+) -> PyResult<Py<PyAny>> {
+    Python::attach(|py| {
+        let tgtg_client_fun: Py<PyAny> = PyModule::from_code(
+            py,
+            c_str!("
+from tgtg import TgtgClient
+def get_client(access_token, refresh_token, user_id, cookie):
+    client = TgtgClient(access_token=access_token, refresh_token=refresh_token, user_id=user_id, cookie=cookie)
+    return client"),
+            c_str!("client.py"),
+            c_str!("client"),
+        )?
+        .getattr("get_client")?
+        .into();
+        let args = PyTuple::new(py, [&access_token, &refresh_token, &user_id, &cookie])?;
+        let ret: pyo3::Py<pyo3::PyAny> = tgtg_client_fun.call1(py, args)?;
+        Ok(ret)
+    })
+}
+
+fn init_fetch_func() -> PyResult<pyo3::Py<pyo3::PyAny>> {
+    Python::attach(|py| {
+        let func = PyModule::from_code(
+            py,
+            c_str!("
+import json
+def fetch_items(client, latitude, longitude, radius):
+    items = client.get_items(
+        favorites_only=False,
+        latitude=latitude,
+        longitude=longitude,
+        page_size=100,
+        radius=radius,
+    )
+    return json.dumps(items)"),
+    c_str!("fetch.py"),
+    c_str!("fetch"),
+        )?
+        .getattr("fetch_items")?
+        .into();
+        Ok(func)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_python() -> PyResult<()> {
+        check_python()
+    }
+
+    #[test]
+    fn test_tgtg_module() -> PyResult<()> {
+        Python::attach(|py| {
+            py.import("tgtg")?;
+            Ok(())
+        })
+    }
+}